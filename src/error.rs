@@ -0,0 +1,125 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::models::status::Status;
+use crate::query::QueryError;
+
+/// Errors returned by the todo list library.
+#[derive(Debug)]
+pub enum TodoError {
+    /// No task matches the given ID or name.
+    NotFound(String),
+    /// More than one task has the given name.
+    Ambiguous { name: String, ids: Vec<u64> },
+    /// A task with this name already exists.
+    Duplicate { name: String, id: u64 },
+    /// The operation would overwrite or contradict existing data.
+    Conflict(String),
+    /// The configured transitions do not allow task `id` to go from `from`
+    /// to `to`; `allowed` lists where it can go instead.
+    Transition { id: u64, from: Status, to: Status, allowed: Vec<Status> },
+    /// A date could not be parsed.
+    InvalidDate(String),
+    /// A select query could not be parsed.
+    Query(QueryError),
+    /// The task file could not be read or written.
+    Storage(io::Error),
+    /// The task file is not valid JSON for a task list.
+    Corrupt(serde_json::Error),
+    /// The task file was written by a newer version of this program.
+    UnsupportedVersion { found: u64, supported: u64 },
+    /// An older task file could not be brought up to date.
+    Migration { from: u64, message: String },
+    /// The configured store cannot do what was asked.
+    Unsupported(String),
+    /// Another process kept the task file locked for longer than `timeout`.
+    Locked { path: PathBuf, timeout: Duration },
+}
+
+impl fmt::Display for TodoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(key) => write!(f, "Task not found: {key}"),
+            Self::Ambiguous { name, ids } => {
+                let ids: Vec<String> = ids.iter().map(u64::to_string).collect();
+                write!(f, "Task name \"{name}\" is ambiguous, use one of the IDs: {}", ids.join(", "))
+            }
+            Self::Duplicate { name, id } => {
+                write!(f, "A task named \"{name}\" already exists (id {id})")
+            }
+            Self::Conflict(message) => write!(f, "Refusing to continue: {message}"),
+            Self::Transition { id, from, to, allowed } => {
+                write!(f, "Task #{id} cannot go from {from} to {to}")?;
+                if allowed.is_empty() {
+                    write!(f, "; {from} is final")
+                } else {
+                    let allowed: Vec<&str> = allowed.iter().map(|status| status.as_str()).collect();
+                    write!(f, "; it can go to {}", allowed.join(", "))
+                }
+            }
+            Self::InvalidDate(e) => write!(f, "Invalid date: {e}"),
+            Self::Query(e) => write!(f, "Error parsing query: {e}"),
+            Self::Storage(e) => write!(f, "Storage error: {e}"),
+            Self::Corrupt(e) => write!(f, "Error parsing JSON: {e}"),
+            Self::UnsupportedVersion { found, supported } => write!(
+                f,
+                "The task file has schema version {found}, but this version of todolist only supports up to {supported}"
+            ),
+            Self::Migration { from, message } => {
+                write!(f, "Could not migrate the task file from schema version {from}: {message}")
+            }
+            Self::Unsupported(message) => write!(f, "Not supported: {message}"),
+            Self::Locked { path, timeout } => write!(
+                f,
+                "{} is locked by another todolist process (waited {:.1}s)",
+                path.display(),
+                timeout.as_secs_f64()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TodoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Query(e) => Some(e),
+            Self::Storage(e) => Some(e),
+            Self::Corrupt(e) => Some(e),
+            Self::NotFound(_)
+            | Self::Ambiguous { .. }
+            | Self::Duplicate { .. }
+            | Self::Conflict(_)
+            | Self::Transition { .. }
+            | Self::InvalidDate(_)
+            | Self::UnsupportedVersion { .. }
+            | Self::Migration { .. }
+            | Self::Unsupported(_)
+            | Self::Locked { .. } => None,
+        }
+    }
+}
+
+impl From<QueryError> for TodoError {
+    fn from(e: QueryError) -> Self {
+        Self::Query(e)
+    }
+}
+
+impl From<io::Error> for TodoError {
+    fn from(e: io::Error) -> Self {
+        Self::Storage(e)
+    }
+}
+
+impl From<rusqlite::Error> for TodoError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Storage(io::Error::other(e))
+    }
+}
+
+impl From<serde_json::Error> for TodoError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Corrupt(e)
+    }
+}
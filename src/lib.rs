@@ -0,0 +1,15 @@
+//! Task list storage and querying behind the `todolist` command line tool.
+//!
+//! [`TodoListService`] holds the tasks and never prints; failures are
+//! returned as [`TodoError`]. The clock and the zone that dates in queries
+//! are read in still follow `TODO_NOW` and `TODO_TZ` (see [`utils::date`]).
+
+mod error;
+pub mod models;
+pub mod query;
+pub mod services;
+pub mod storage;
+pub mod utils;
+
+pub use error::TodoError;
+pub use services::TodoListService;
@@ -1,10 +1,16 @@
-mod models;
-mod services;
-mod utils;
-
 use clap::{Parser, Subcommand};
-use services::TodoListService;
 use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+use todolist::models::status::{Status, Transitions};
+use todolist::models::task::{Task, TaskChanges};
+use todolist::utils::date;
+use todolist::utils::timezone::{self, Zone};
+use todolist::storage::{schema, JsonFileStore, StoreKind, DEFAULT_LOCK_TIMEOUT};
+use todolist::services::{History, Journal, UNKNOWN_USER};
+use todolist::{TodoError, TodoListService};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -21,72 +27,524 @@ enum Commands {
         name: String,
         /// A description of the task
         description: String,
-        /// The due date of the task in format DD-MM-YYYY HH:MM
+        /// The due date of the task in format DD-MM-YYYY HH:MM or a relative or
+        /// natural-language expression such as "tomorrow 09:00", "now + 3d",
+        /// "in 2 hours", "fri 5pm", "3rd of next month" or "eow"
         date: String,
         /// The category of the task
         category: String,
+        /// Add the task even if another task already has the same name
+        #[arg(long)]
+        force: bool,
     },
     /// Mark a task as done
     Done {
-        /// The name of the task to mark as done
-        name: String,
+        /// The ID or unambiguous name of the task to mark as done
+        task: String,
+    },
+    /// Mark a task as in progress
+    Start {
+        /// The ID or unambiguous name of the task to start
+        task: String,
+    },
+    /// Mark a task as blocked
+    Block {
+        /// The ID or unambiguous name of the blocked task
+        task: String,
+    },
+    /// Mark a task as waiting, e.g. on someone else
+    Wait {
+        /// The ID or unambiguous name of the waiting task
+        task: String,
+    },
+    /// Mark a task as cancelled
+    Cancel {
+        /// The ID or unambiguous name of the task to cancel
+        task: String,
     },
-    /// Update an existing task
+    /// Set a done or cancelled task back to pending
+    #[command(visible_alias = "undone")]
+    Reopen {
+        /// The ID or unambiguous name of the task to reopen
+        task: String,
+    },
+    /// Update an existing task. Without any field flags the new values are
+    /// read from stdin, prompting for each one when stdin is a terminal.
     Update {
-        /// The name of the task to update
-        name: String,
+        /// The ID or unambiguous name of the task to update
+        task: String,
+        /// New name for the task
+        #[arg(long)]
+        name: Option<String>,
+        /// New description for the task
+        #[arg(long)]
+        description: Option<String>,
+        /// New due date, in any format accepted by `add`
+        #[arg(long)]
+        date: Option<String>,
+        /// New category for the task
+        #[arg(long)]
+        category: Option<String>,
+        /// New status: pending, `in_progress`, blocked, waiting, done or
+        /// cancelled
+        #[arg(long)]
+        status: Option<Status>,
     },
-    /// Delete a task from the todo list
+    /// Move a task to the trash
     Delete {
-        /// The name of the task to delete
-        name: String,
+        /// The ID or unambiguous name of the task to delete
+        task: String,
+    },
+    /// Take a task out of the trash
+    Restore {
+        /// The ID or unambiguous name of the trashed task
+        task: String,
+    },
+    /// Show who changed a task, what and when
+    History {
+        /// The ID or unambiguous name of the task
+        task: String,
+    },
+    /// List or empty the trash
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommands,
     },
     /// Select and display tasks based on a predicate
     Select {
         /// The predicate to filter tasks. Use '\*' to select all tasks.
         /// For filtering, use the format: "* where <condition>"
-        /// Example: "\* where date < '2023-12-31 00:00' and category=work and status=on and description like project"
+        /// Example: "\* where date < '31-12-2023 00:00' and category=work and status=on and description like project"
         predicate: Vec<String>,
+        /// Also show tasks in the trash
+        #[arg(long)]
+        include_trash: bool,
+        /// Show the tasks as they were at this date (event log store only),
+        /// e.g. "01-03-2024 00:00"
+        #[arg(long, value_name = "DATE")]
+        as_of: Option<String>,
+    },
+    /// Recover the readable tasks from a damaged task file. The damaged file
+    /// is kept as a timestamped `.corrupt` copy.
+    Repair,
+    /// Copy the tasks of a JSON task file into the configured store, keeping
+    /// their IDs
+    Import {
+        /// The JSON file to import, e.g. an existing db.json
+        file: PathBuf,
+        /// Replace the tasks already in the store
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Upgrade the task file to the current schema version. Loading the file
+    /// does this automatically; the original is kept as `<file>.v<N>.bak`.
+    Migrate {
+        /// Only list the steps that would run
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Revert the last changes made by add, done, update and delete
+    Undo {
+        /// How many changes to revert
+        #[arg(default_value_t = 1)]
+        steps: usize,
+        /// Only show what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Reapply changes reverted by undo
+    Redo {
+        /// How many changes to reapply
+        #[arg(default_value_t = 1)]
+        steps: usize,
+        /// Only show what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TrashCommands {
+    /// Show the tasks in the trash
+    List,
+    /// Permanently remove the tasks in the trash
+    Empty {
+        /// Only remove tasks deleted longer ago than this, e.g. 30d or 2w
+        #[arg(long, value_name = "AGE")]
+        older_than: Option<String>,
     },
 }
 
-fn main() {
+fn main() -> ExitCode {
     env::set_var("APP_ENV", "production");
 
     let cli = Cli::parse();
-    let mut service = TodoListService::load_from_file();
+    let zone = Zone::from_env().unwrap_or_else(|e| {
+        eprintln!("{e}. Using the system time zone.");
+        Zone::Local
+    });
+    let result = match cli.command {
+        Some(Commands::Repair) => repair(&get_file_path(StoreKind::Json)),
+        Some(Commands::Migrate { dry_run }) => migrate(&get_file_path(StoreKind::Json), dry_run),
+        _ => load_service().and_then(|mut service| run(&cli, &mut service, zone)),
+    };
 
-    match &cli.command {
-        Some(Commands::Add { name, description, date, category }) => {
-
-            match utils::date::parse(date) {
-                Ok(parsed_date) => {
-                    service.add_task(name.clone(), description.clone(), parsed_date, category.clone());
-                },
-                Err(e) => {
-                    eprintln!("Error parsing date: {e}");
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            match (&e, &cli.command) {
+                (TodoError::Query(query_error), Some(Commands::Select { predicate, .. })) => {
+                    eprintln!("Error parsing query: {}", query_error.render(&select_input(predicate)));
                 }
+                (TodoError::Duplicate { .. }, _) => eprintln!("{e}. Use --force to add it anyway."),
+                _ => eprintln!("{e}"),
             }
+            ExitCode::from(exit_code(&e))
         }
-        Some(Commands::Done { name }) => {
-            service.mark_done(name);
+    }
+}
+
+/// Exit codes, as documented in the README. Clap itself exits with 2 on
+/// invalid arguments.
+const fn exit_code(error: &TodoError) -> u8 {
+    match error {
+        TodoError::NotFound(_) | TodoError::Ambiguous { .. } => 3,
+        TodoError::Duplicate { .. } | TodoError::Conflict(_) | TodoError::Transition { .. } => 4,
+        TodoError::InvalidDate(_) => 5,
+        TodoError::Storage(_)
+        | TodoError::Corrupt(_)
+        | TodoError::UnsupportedVersion { .. }
+        | TodoError::Migration { .. } => 6,
+        TodoError::Query(_) => 7,
+        TodoError::Locked { .. } => 8,
+        TodoError::Unsupported(_) => 9,
+    }
+}
+
+fn run(cli: &Cli, service: &mut TodoListService, zone: Zone) -> Result<(), TodoError> {
+    match &cli.command {
+        Some(Commands::Add { name, description, date, category, force }) => {
+            let date = date::parse(date).map_err(TodoError::InvalidDate)?;
+            let task = service.add_task(name.clone(), description.clone(), date, category.clone(), *force)?;
+            println!("Task added successfully! (id {})", task.id);
         }
-        Some(Commands::Update { name }) => {
-            service.update_task(name);
+        Some(Commands::Done { task }) => {
+            service.mark_done(task)?;
+            println!("Task marked as done!");
         }
-        Some(Commands::Delete { name }) => {
-            service.delete_task(name);
+        Some(Commands::Start { task }) => set_status(service, task, Status::InProgress)?,
+        Some(Commands::Block { task }) => set_status(service, task, Status::Blocked)?,
+        Some(Commands::Wait { task }) => set_status(service, task, Status::Waiting)?,
+        Some(Commands::Cancel { task }) => set_status(service, task, Status::Cancelled)?,
+        Some(Commands::Reopen { task }) => {
+            service.reopen_task(task)?;
+            println!("Task reopened!");
         }
-        Some(Commands::Select { predicate }) => {
-            let predicate_str = if predicate.is_empty() {
-                "*".to_string()
-            } else {
-                predicate.join(" ")
+        Some(Commands::Update { task, name, description, date, category, status }) => {
+            let date = date.as_deref().map(date::parse).transpose().map_err(TodoError::InvalidDate)?;
+            let changes = TaskChanges {
+                name: name.clone(),
+                description: description.clone(),
+                date,
+                category: category.clone(),
+                status: *status,
             };
-            service.select_tasks(&predicate_str);
+            update(service, task, changes, zone)?;
+        }
+        Some(Commands::Delete { task }) => {
+            let task = service.delete_task(task)?;
+            println!("Task moved to the trash. Run `todolist restore {}` to bring it back.", task.id);
+        }
+        Some(Commands::Restore { task }) => {
+            service.restore_task(task)?;
+            println!("Task restored!");
+        }
+        Some(Commands::History { task }) => {
+            let changes = service.history(task)?;
+            if changes.is_empty() {
+                println!("No recorded changes.");
+            }
+            for change in changes {
+                println!("{}  {}  {}  {}", timezone::format(change.at, zone), change.user, change.action, change.describe());
+            }
+        }
+        Some(Commands::Trash { command: TrashCommands::List }) => {
+            let tasks: Vec<&Task> = service.trash().collect();
+            if tasks.is_empty() {
+                println!("The trash is empty.");
+            }
+            for task in tasks {
+                println!("{:#?}", task.in_zone(zone));
+            }
+        }
+        Some(Commands::Trash { command: TrashCommands::Empty { older_than } }) => {
+            let cutoff = older_than.as_deref()
+                .map(|age| date::ago(age, date::now()).map_err(TodoError::InvalidDate))
+                .transpose()?;
+            let removed = service.empty_trash(cutoff)?;
+            println!("Permanently removed {} task(s).", removed.len());
+        }
+        Some(Commands::Select { predicate, include_trash, as_of: None }) => {
+            let input = select_input(predicate);
+            let tasks = if *include_trash { service.select_with_trash(&input)? } else { service.select(&input)? };
+            print_tasks(&tasks, zone);
+        }
+        Some(Commands::Select { predicate, include_trash, as_of: Some(at) }) => {
+            let at = date::parse(at).map_err(TodoError::InvalidDate)?;
+            let input = select_input(predicate);
+            let tasks = if *include_trash { service.select_as_of_with_trash(at, &input)? } else { service.select_as_of(at, &input)? };
+            print_tasks(&tasks.iter().collect::<Vec<_>>(), zone);
+        }
+        Some(Commands::Import { file, replace }) => {
+            let list = JsonFileStore::read(file)?;
+            let imported = service.import(list, *replace)?;
+            println!("Imported {imported} task(s) from {}.", file.display());
+        }
+        Some(Commands::Undo { steps, dry_run }) => {
+            service.check_journal()?;
+            let preview: Vec<String> = service.undoable(*steps).map(|operation| {
+                format!("  undo {} of #{}: {}", operation.kind, operation.id(), operation.describe(true))
+            }).collect();
+            apply_steps(&preview, *dry_run, "undo", || service.undo(*steps).map(|_| ()))?;
+        }
+        Some(Commands::Redo { steps, dry_run }) => {
+            service.check_journal()?;
+            let preview: Vec<String> = service.redoable(*steps).map(|operation| {
+                format!("  redo {} of #{}: {}", operation.kind, operation.id(), operation.describe(false))
+            }).collect();
+            apply_steps(&preview, *dry_run, "redo", || service.redo(*steps).map(|_| ()))?;
+        }
+        Some(Commands::Repair | Commands::Migrate { .. }) => {
+            unreachable!("repair and migrate run without loading the task file")
         }
         None => {
             println!("No command was used");
         }
     }
+    Ok(())
+}
+
+/// Applies `changes`, or the ones read by [`prompt_changes`] if there are
+/// none.
+fn update(service: &mut TodoListService, key: &str, changes: TaskChanges, zone: Zone) -> Result<(), TodoError> {
+    let changes = if changes.is_empty() {
+        prompt_changes(service.find_task(key)?, zone)
+    } else {
+        changes
+    };
+    if changes.is_empty() {
+        println!("No changes made.");
+        return Ok(());
+    }
+    service.update_task(key, changes)?;
+    println!("Task updated successfully!");
+    Ok(())
+}
+
+fn set_status(service: &mut TodoListService, key: &str, status: Status) -> Result<(), TodoError> {
+    let task = service.set_status(key, status)?;
+    println!("Task #{} is now {}.", task.id, task.status);
+    Ok(())
+}
+
+/// Shows what an undo or redo will change before `apply` makes the change.
+fn apply_steps(preview: &[String], dry_run: bool, action: &str, apply: impl FnOnce() -> Result<(), TodoError>) -> Result<(), TodoError> {
+    if preview.is_empty() {
+        println!("Nothing to {action}.");
+        return Ok(());
+    }
+    println!("{}", preview.join("\n"));
+    if dry_run {
+        println!("Dry run: nothing was changed.");
+        return Ok(());
+    }
+    apply()?;
+    println!("Done.");
+    Ok(())
+}
+
+fn print_tasks(tasks: &[&Task], zone: Zone) {
+    if tasks.is_empty() {
+        println!("No tasks match the given criteria.");
+    }
+    for task in tasks {
+        println!("{:#?}", task.in_zone(zone));
+    }
+}
+
+fn select_input(predicate: &[String]) -> String {
+    if predicate.is_empty() {
+        "*".to_string()
+    } else {
+        predicate.join(" ")
+    }
+}
+
+/// The task file: `TODO_FILE` if set, otherwise `db.json` (`db.sqlite` for
+/// the SQLite store, with a `test_` prefix when `APP_ENV=test`) in the
+/// working directory.
+fn get_file_path(kind: StoreKind) -> PathBuf {
+    let env = env::var("APP_ENV").unwrap_or_else(|_| "production".to_string());
+    let file_name = match (env.as_str(), kind) {
+        ("test", StoreKind::Sqlite) => "test_db.sqlite",
+        (_, StoreKind::Sqlite) => "db.sqlite",
+        ("test", StoreKind::Events) => "test_db.jsonl",
+        (_, StoreKind::Events) => "db.jsonl",
+        ("test", _) => "test_db.json",
+        _ => "db.json",
+    };
+    env::var("TODO_FILE").map_or_else(|_| PathBuf::from(file_name), PathBuf::from)
+}
+
+/// The storage backend: `TODO_STORE` (`json`, `memory`, `sqlite` or
+/// `events`),
+/// defaulting to the JSON file.
+fn store_kind() -> StoreKind {
+    env::var("TODO_STORE").map_or_else(|_| StoreKind::default(), |name| {
+        name.parse().unwrap_or_else(|e| {
+            eprintln!("{e}. Using the JSON file.");
+            StoreKind::default()
+        })
+    })
+}
+
+/// How long to wait for another `todolist` process to finish with the task
+/// file: `TODO_LOCK_TIMEOUT` in seconds, or the library default.
+fn lock_timeout() -> Duration {
+    let Ok(value) = env::var("TODO_LOCK_TIMEOUT") else {
+        return DEFAULT_LOCK_TIMEOUT;
+    };
+    value.trim().parse().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok()).unwrap_or_else(|| {
+        eprintln!("Invalid TODO_LOCK_TIMEOUT `{value}`. Using {}s.", DEFAULT_LOCK_TIMEOUT.as_secs());
+        DEFAULT_LOCK_TIMEOUT
+    })
+}
+
+/// Opens the task file. A file that cannot be parsed is left as it is and
+/// also copied aside, so that a later `repair` cannot lose anything.
+fn load_service() -> Result<TodoListService, TodoError> {
+    let kind = store_kind();
+    let path = get_file_path(kind);
+    let store = kind.open(&path, lock_timeout())?;
+    let service = TodoListService::with_store(store).inspect_err(|e| {
+        if kind == StoreKind::Json && matches!(e, TodoError::Corrupt(_)) {
+            match JsonFileStore::preserve_corrupt(&path) {
+                Ok(backup) => eprintln!("The task file was not changed; a copy was saved as {}.", backup.display()),
+                Err(e) => eprintln!("The task file was not changed, but it could not be copied: {e}"),
+            }
+            eprintln!("Run `todolist repair` to recover the readable tasks.");
+        }
+    })?;
+    let service = service.with_user(current_user()).with_transitions(transitions());
+    match kind {
+        StoreKind::Memory => Ok(service),
+        _ => Ok(service.with_journal(Journal::path_for(&path))?.with_history(History::path_for(&path))),
+    }
+}
+
+/// Which status changes are allowed: the rules in `TODO_TRANSITIONS` (see
+/// [`Transitions`]), or the default ones.
+fn transitions() -> Transitions {
+    env::var("TODO_TRANSITIONS").map_or_else(|_| Transitions::default(), |rules| {
+        rules.parse().unwrap_or_else(|e| {
+            eprintln!("Invalid TODO_TRANSITIONS: {e}. Using the default transitions.");
+            Transitions::default()
+        })
+    })
+}
+
+/// Who changes are recorded as made by: `TODO_USER`, or else the login name.
+fn current_user() -> String {
+    ["TODO_USER", "USER", "USERNAME"]
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|user| !user.trim().is_empty()))
+        .unwrap_or_else(|| UNKNOWN_USER.to_string())
+}
+
+fn repair(path: &Path) -> Result<(), TodoError> {
+    match JsonFileStore::repair(path, lock_timeout())? {
+        Some(repair) => println!(
+            "Recovered {} task(s) into {}. The damaged file was kept as {}.",
+            repair.recovered,
+            path.display(),
+            repair.backup.display()
+        ),
+        None => println!("Nothing to repair: {} loads correctly.", path.display()),
+    }
+    Ok(())
+}
+
+fn migrate(path: &Path, dry_run: bool) -> Result<(), TodoError> {
+    let mut store = JsonFileStore::open(path, lock_timeout())?;
+    let plan = store.plan()?;
+    if plan.steps.is_empty() {
+        println!("{} is up to date (schema version {}).", path.display(), plan.from);
+        return Ok(());
+    }
+
+    println!(
+        "{} is at schema version {}; {} to version {}:",
+        path.display(),
+        plan.from,
+        if dry_run { "migrating it would upgrade it" } else { "upgrading it" },
+        schema::CURRENT_VERSION
+    );
+    for step in &plan.steps {
+        println!("  {} -> {}: {}", step.from, step.from + 1, step.description);
+    }
+    if dry_run {
+        println!("Dry run: {} was not changed.", path.display());
+    } else if let Some(backup) = store.migrate()? {
+        println!("Done. The original file was kept as {}.", backup.display());
+    }
+    Ok(())
+}
+
+/// Reads new values for a task from stdin. Prompts are only shown when stdin
+/// is a terminal, so answers can also be piped in. Blank answers keep the
+/// current value.
+fn prompt_changes(task: &Task, zone: Zone) -> TaskChanges {
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        println!("Enter new details (press Enter to keep current value):");
+    }
+
+    let new_name = prompt(interactive, &format!("New name ({}): ", task.name));
+    let new_description = prompt(interactive, &format!("New description ({}): ", task.description));
+    let new_date_string = prompt(
+        interactive,
+        &format!("New date as 'DD-MM-YYYY HH:MM' or e.g. 'fri 5pm', 'in 2 hours' ({}): ", zone.localize(task.date).to_rfc2822()),
+    );
+
+    let new_date = if new_date_string.is_empty() {
+        None
+    } else {
+        match date::parse(new_date_string.as_str()) {
+            Ok(parsed_date) => Some(parsed_date),
+            Err(e) => {
+                eprintln!("Error parsing date: {e}");
+                None
+            }
+        }
+    };
+
+    let new_category = prompt(interactive, &format!("New category ({}): ", task.category));
+
+    TaskChanges {
+        name: Some(new_name).filter(|name| !name.is_empty() && *name != task.name),
+        description: Some(new_description).filter(|description| !description.is_empty()),
+        date: new_date.filter(|date| *date != task.date),
+        category: Some(new_category).filter(|category| !category.is_empty()),
+        status: None,
+    }
+}
+
+fn prompt(interactive: bool, message: &str) -> String {
+    if interactive {
+        print!("{message}");
+        io::stdout().flush().expect("Failed to flush stdout");
+    }
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).expect("Failed to read line from stdin");
+    answer.trim().to_string()
 }
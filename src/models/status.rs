@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+/// Where a task is in its lifecycle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    #[default]
+    Pending,
+    InProgress,
+    Blocked,
+    Waiting,
+    Done,
+    Cancelled,
+}
+
+impl Status {
+    pub const ALL: [Self; 6] = [Self::Pending, Self::InProgress, Self::Blocked, Self::Waiting, Self::Done, Self::Cancelled];
+
+    /// The name the status is stored and shown under.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::InProgress => "in_progress",
+            Self::Blocked => "blocked",
+            Self::Waiting => "waiting",
+            Self::Done => "done",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    /// Done and cancelled tasks need no more work.
+    #[must_use]
+    pub const fn is_closed(self) -> bool {
+        matches!(self, Self::Done | Self::Cancelled)
+    }
+
+    /// The statuses a query for `value` matches. Besides the names accepted
+    /// by [`FromStr`], `open` stands for every status that is not closed, and
+    /// so do `off` and `false`: before there were statuses they selected all
+    /// the work that was not done.
+    ///
+    /// # Errors
+    ///
+    /// Returns the [`FromStr`] error for anything else.
+    pub fn parse_filter(value: &str) -> Result<Vec<Self>, String> {
+        match value.to_lowercase().as_str() {
+            "open" | "off" | "false" => Ok(Self::ALL.into_iter().filter(|status| !status.is_closed()).collect()),
+            _ => value.parse().map(|status| vec![status]),
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+
+    /// Parses a status name, ignoring case and accepting `-` for `_`. The
+    /// completion flags of old versions, `on`/`true` and `off`/`false`, mean
+    /// `done` and `pending`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().replace('-', "_").as_str() {
+            "pending" | "off" | "false" => Ok(Self::Pending),
+            "in_progress" | "started" => Ok(Self::InProgress),
+            "blocked" => Ok(Self::Blocked),
+            "waiting" => Ok(Self::Waiting),
+            "done" | "on" | "true" => Ok(Self::Done),
+            "cancelled" | "canceled" => Ok(Self::Cancelled),
+            _ => Err(format!("invalid status `{value}`, expected pending, in_progress, blocked, waiting, done or cancelled")),
+        }
+    }
+}
+
+/// Also reads the `true`/`false` completion flag that older task files,
+/// event logs and journals contain.
+impl<'de> Deserialize<'de> for Status {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Stored {
+            Flag(bool),
+            Name(String),
+        }
+
+        match Stored::deserialize(deserializer)? {
+            Stored::Flag(true) => Ok(Self::Done),
+            Stored::Flag(false) => Ok(Self::Pending),
+            Stored::Name(name) => name.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// The status changes a task may go through. Staying in the same status is
+/// always allowed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transitions {
+    allowed: BTreeMap<Status, BTreeSet<Status>>,
+}
+
+impl Transitions {
+    #[must_use]
+    pub fn allows(&self, from: Status, to: Status) -> bool {
+        from == to || self.allowed.get(&from).is_some_and(|targets| targets.contains(&to))
+    }
+
+    /// The statuses a task in `from` may move to.
+    pub fn from(&self, from: Status) -> impl Iterator<Item = Status> + '_ {
+        self.allowed.get(&from).into_iter().flatten().copied()
+    }
+}
+
+impl Default for Transitions {
+    /// Open tasks may move to any other status; done and cancelled tasks
+    /// only back to pending.
+    fn default() -> Self {
+        let allowed = Status::ALL
+            .into_iter()
+            .map(|from| {
+                let targets = if from.is_closed() {
+                    BTreeSet::from([Status::Pending])
+                } else {
+                    Status::ALL.into_iter().filter(|to| *to != from).collect()
+                };
+                (from, targets)
+            })
+            .collect();
+        Self { allowed }
+    }
+}
+
+impl FromStr for Transitions {
+    type Err = String;
+
+    /// Parses rules of the form `from: to, to; from: to`, e.g.
+    /// `pending: in_progress; in_progress: done, blocked; blocked: in_progress`.
+    /// A status without a rule cannot be left.
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut allowed: BTreeMap<Status, BTreeSet<Status>> = BTreeMap::new();
+        for rule in spec.split(';').map(str::trim).filter(|rule| !rule.is_empty()) {
+            let Some((from, targets)) = rule.split_once(':') else {
+                return Err(format!("expected `status: status, ...`, found `{rule}`"));
+            };
+            let targets = targets
+                .split(',')
+                .map(str::trim)
+                .filter(|target| !target.is_empty())
+                .map(str::parse)
+                .collect::<Result<BTreeSet<Status>, _>>()?;
+            allowed.entry(from.trim().parse()?).or_default().extend(targets);
+        }
+        Ok(Self { allowed })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_names_and_legacy_flags() {
+        for (input, expected) in [
+            ("done", Status::Done),
+            ("ON", Status::Done),
+            ("true", Status::Done),
+            ("off", Status::Pending),
+            ("In-Progress", Status::InProgress),
+            ("canceled", Status::Cancelled),
+        ] {
+            assert_eq!(input.parse(), Ok(expected));
+        }
+        assert!("finished".parse::<Status>().is_err());
+        assert_eq!(Status::parse_filter("Off"), Ok(vec![Status::Pending, Status::InProgress, Status::Blocked, Status::Waiting]));
+        assert_eq!(Status::parse_filter("open"), Status::parse_filter("false"));
+        assert_eq!(Status::parse_filter("on"), Ok(vec![Status::Done]));
+        assert!(Status::parse_filter("finished").is_err());
+
+        let statuses: Vec<Status> = serde_json::from_str(r#"[true, false, "in_progress"]"#).expect("statuses parse");
+        assert_eq!(statuses, [Status::Done, Status::Pending, Status::InProgress]);
+        assert_eq!(serde_json::to_string(&Status::InProgress).expect("status serializes"), r#""in_progress""#);
+    }
+
+    #[test]
+    fn test_transitions() {
+        let default = Transitions::default();
+        assert!(default.allows(Status::Pending, Status::Blocked));
+        assert!(default.allows(Status::Done, Status::Pending));
+        assert!(!default.allows(Status::Done, Status::InProgress));
+        assert!(default.allows(Status::Cancelled, Status::Cancelled));
+
+        let custom: Transitions = "pending: in_progress; in_progress: done, blocked ; blocked: in_progress".parse().expect("rules parse");
+        assert!(custom.allows(Status::InProgress, Status::Blocked));
+        assert!(!custom.allows(Status::Pending, Status::Done));
+        assert_eq!(custom.from(Status::Done).count(), 0);
+
+        assert!("pending in_progress".parse::<Transitions>().is_err());
+        assert!("pending: finished".parse::<Transitions>().is_err());
+    }
+}
@@ -1,9 +1,16 @@
+use std::fmt;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use crate::utils::datetime_format;
+use super::status::Status;
+use crate::utils::timezone::{self, Zone};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Task {
+    /// Stable identifier; `0` only while loading files that predate IDs.
+    #[serde(default)]
+    pub id: u64,
+
     pub name: String,
     pub description: String,
 
@@ -12,5 +19,111 @@ pub struct Task {
 
     pub category: String,
 
-    pub status: bool,
+    pub status: Status,
+
+    /// When the task was moved to the trash. Trashed tasks are left out of
+    /// everything but the trash commands until restored.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "datetime_format::optional")]
+    pub deleted: Option<DateTime<Utc>>,
+
+    /// When the task was last reopened after being done or cancelled.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "datetime_format::optional")]
+    pub reopened: Option<DateTime<Utc>>,
+}
+
+impl Task {
+    #[must_use]
+    pub const fn is_done(&self) -> bool {
+        matches!(self.status, Status::Done)
+    }
+
+    #[must_use]
+    pub const fn is_trashed(&self) -> bool {
+        self.deleted.is_some()
+    }
+
+    /// The fields by name, in their stored form, as shown when comparing
+    /// versions of a task. `deleted` and `reopened` are `None` until set.
+    #[must_use]
+    pub fn fields(&self) -> [(&'static str, Option<String>); 7] {
+        [
+            ("name", Some(self.name.clone())),
+            ("description", Some(self.description.clone())),
+            ("date", Some(datetime_format::format(self.date))),
+            ("category", Some(self.category.clone())),
+            ("status", Some(self.status.to_string())),
+            ("deleted", self.deleted.map(datetime_format::format)),
+            ("reopened", self.reopened.map(datetime_format::format)),
+        ]
+    }
+
+    /// A view of the task that renders its date in `zone`.
+    #[must_use]
+    pub const fn in_zone(&self, zone: Zone) -> TaskView<'_> {
+        TaskView { task: self, zone }
+    }
+}
+
+/// A partial update of a task; `None` fields are left unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskChanges {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub date: Option<DateTime<Utc>>,
+    pub category: Option<String>,
+    pub status: Option<Status>,
+}
+
+impl TaskChanges {
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.description.is_none()
+            && self.date.is_none()
+            && self.category.is_none()
+            && self.status.is_none()
+    }
+
+    pub fn apply_to(self, task: &mut Task) {
+        if let Some(name) = self.name {
+            task.name = name;
+        }
+        if let Some(description) = self.description {
+            task.description = description;
+        }
+        if let Some(date) = self.date {
+            task.date = date;
+        }
+        if let Some(category) = self.category {
+            task.category = category;
+        }
+        if let Some(status) = self.status {
+            task.status = status;
+        }
+    }
+}
+
+pub struct TaskView<'a> {
+    task: &'a Task,
+    zone: Zone,
+}
+
+impl fmt::Debug for TaskView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Task");
+        debug
+            .field("id", &self.task.id)
+            .field("name", &self.task.name)
+            .field("description", &self.task.description)
+            .field("date", &format_args!("{}", timezone::format(self.task.date, self.zone)))
+            .field("category", &self.task.category)
+            .field("status", &format_args!("{}", self.task.status));
+        if let Some(reopened) = self.task.reopened {
+            debug.field("reopened", &format_args!("{}", timezone::format(reopened, self.zone)));
+        }
+        if let Some(deleted) = self.task.deleted {
+            debug.field("deleted", &format_args!("{}", timezone::format(deleted, self.zone)));
+        }
+        debug.finish()
+    }
 }
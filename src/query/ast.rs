@@ -0,0 +1,321 @@
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use crate::models::status::Status;
+use crate::models::task::Task;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Id,
+    Name,
+    Description,
+    Date,
+    Category,
+    Status,
+}
+
+impl Field {
+    #[must_use]
+    pub fn from_keyword(word: &str) -> Option<Self> {
+        match word.to_lowercase().as_str() {
+            "id" => Some(Self::Id),
+            "name" => Some(Self::Name),
+            "description" => Some(Self::Description),
+            "date" => Some(Self::Date),
+            "category" => Some(Self::Category),
+            "status" => Some(Self::Status),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Name => "name",
+            Self::Description => "description",
+            Self::Date => "date",
+            Self::Category => "category",
+            Self::Status => "status",
+        }
+    }
+
+    #[must_use]
+    pub fn compare(self, a: &Task, b: &Task) -> Ordering {
+        match self {
+            Self::Id => a.id.cmp(&b.id),
+            Self::Name => a.name.cmp(&b.name),
+            Self::Description => a.description.cmp(&b.description),
+            Self::Date => a.date.cmp(&b.date),
+            Self::Category => a.category.cmp(&b.category),
+            Self::Status => a.status.cmp(&b.status),
+        }
+    }
+
+    #[must_use]
+    pub const fn supports(self, operator: Operator) -> bool {
+        match self {
+            Self::Name | Self::Description | Self::Category => true,
+            Self::Id | Self::Date => !operator.is_text_only(),
+            Self::Status => matches!(operator, Operator::Eq | Operator::Ne | Operator::In),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Ge,
+    Gt,
+    Like,
+    NotLike,
+    StartsWith,
+    EndsWith,
+    In,
+    Matches,
+}
+
+impl Operator {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Eq => "=",
+            Self::Ne => "!=",
+            Self::Ge => ">=",
+            Self::Gt => ">",
+            Self::Like => "like",
+            Self::NotLike => "not like",
+            Self::StartsWith => "starts with",
+            Self::EndsWith => "ends with",
+            Self::In => "in",
+            Self::Matches => "matches",
+        }
+    }
+
+    /// Substring and pattern operators that only make sense on text fields.
+    #[must_use]
+    pub const fn is_text_only(self) -> bool {
+        matches!(self, Self::Like | Self::NotLike | Self::StartsWith | Self::EndsWith | Self::Matches)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Literal {
+    Text(String),
+    Date(DateTime<Utc>),
+    Status(Status),
+    Number(u64),
+    /// The right-hand side of `in (a, b, c)`.
+    List(Vec<Self>),
+    /// The right-hand side of `matches /regex/flags`, compiled once at parse time.
+    Pattern(Pattern),
+}
+
+/// A compiled regular expression. Flags are folded into the pattern as an
+/// inline group, so two patterns are equal when their sources are.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    /// Compiles `source` with the given `i`, `m` and `s` flags.
+    ///
+    /// # Errors
+    ///
+    /// Returns the regex error if `source` is not a valid pattern.
+    pub fn new(source: &str, flags: &str) -> Result<Self, regex::Error> {
+        let pattern = if flags.is_empty() {
+            source.to_string()
+        } else {
+            format!("(?{flags}){source}")
+        };
+        Regex::new(&pattern).map(Self)
+    }
+
+    #[must_use]
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.0.is_match(haystack)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for Pattern {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub field: Field,
+    pub operator: Operator,
+    pub value: Literal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Condition(Condition),
+    And(Box<Self>, Box<Self>),
+    Or(Box<Self>, Box<Self>),
+    Not(Box<Self>),
+    /// Tasks with an entry in the change history at or after the date. The
+    /// history is not part of a task, so this has to be replaced with an ID
+    /// list (see [`resolve_changes`](Self::resolve_changes)) before
+    /// matching; unresolved, it matches nothing.
+    ChangedSince(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: Field,
+    pub descending: bool,
+}
+
+/// A parsed select query. A query without a filter selects every task.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub filter: Option<Expr>,
+    pub order_by: Vec<SortKey>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+impl Query {
+    #[must_use]
+    pub fn matches(&self, task: &Task) -> bool {
+        self.filter.as_ref().is_none_or(|expr| expr.matches(task))
+    }
+
+    /// Filters, sorts and paginates `tasks`. Without an `order by` clause
+    /// tasks are sorted by date; ties are always broken by name so the
+    /// output is stable between runs.
+    pub fn apply<'a>(&self, tasks: impl IntoIterator<Item = &'a Task>) -> Vec<&'a Task> {
+        let mut selected: Vec<&Task> = tasks.into_iter().filter(|task| self.matches(task)).collect();
+
+        selected.sort_by(|a, b| {
+            let ordering = if self.order_by.is_empty() {
+                Field::Date.compare(a, b)
+            } else {
+                self.order_by.iter().fold(Ordering::Equal, |ordering, key| {
+                    ordering.then_with(|| {
+                        let ordering = key.field.compare(a, b);
+                        if key.descending { ordering.reverse() } else { ordering }
+                    })
+                })
+            };
+            ordering.then_with(|| Field::Name.compare(a, b))
+        });
+
+        selected
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+impl Expr {
+    #[must_use]
+    pub fn matches(&self, task: &Task) -> bool {
+        match self {
+            Self::Condition(condition) => condition.matches(task),
+            Self::And(left, right) => left.matches(task) && right.matches(task),
+            Self::Or(left, right) => left.matches(task) || right.matches(task),
+            Self::Not(inner) => !inner.matches(task),
+            Self::ChangedSince(_) => false,
+        }
+    }
+
+    #[must_use]
+    pub fn mentions_changes(&self) -> bool {
+        match self {
+            Self::Condition(_) => false,
+            Self::And(left, right) | Self::Or(left, right) => left.mentions_changes() || right.mentions_changes(),
+            Self::Not(inner) => inner.mentions_changes(),
+            Self::ChangedSince(_) => true,
+        }
+    }
+
+    /// Replaces every `changed since` with an `id in (...)` condition on the
+    /// IDs `changed` returns for its date.
+    pub fn resolve_changes(&mut self, changed: &impl Fn(DateTime<Utc>) -> BTreeSet<u64>) {
+        match self {
+            Self::Condition(_) => {}
+            Self::And(left, right) | Self::Or(left, right) => {
+                left.resolve_changes(changed);
+                right.resolve_changes(changed);
+            }
+            Self::Not(inner) => inner.resolve_changes(changed),
+            Self::ChangedSince(since) => {
+                *self = Self::Condition(Condition {
+                    field: Field::Id,
+                    operator: Operator::In,
+                    value: Literal::List(changed(*since).into_iter().map(Literal::Number).collect()),
+                });
+            }
+        }
+    }
+}
+
+impl Condition {
+    /// A list matches if any of its items does; with `!=` (as in
+    /// `status != open`), if none does.
+    #[must_use]
+    pub fn matches(&self, task: &Task) -> bool {
+        match &self.value {
+            Literal::List(items) => {
+                let any = items.iter().any(|item| compare(task, self.field, Operator::Eq, item));
+                any != (self.operator == Operator::Ne)
+            }
+            value => compare(task, self.field, self.operator, value),
+        }
+    }
+}
+
+fn compare(task: &Task, field: Field, operator: Operator, value: &Literal) -> bool {
+    match (field, value) {
+        (Field::Name, Literal::Text(value)) => compare_string(&task.name, operator, value),
+        (Field::Description, Literal::Text(value)) => compare_string(&task.description, operator, value),
+        (Field::Category, Literal::Text(value)) => compare_string(&task.category, operator, value),
+        (Field::Name, Literal::Pattern(pattern)) => pattern.is_match(&task.name),
+        (Field::Description, Literal::Pattern(pattern)) => pattern.is_match(&task.description),
+        (Field::Category, Literal::Pattern(pattern)) => pattern.is_match(&task.category),
+        (Field::Date, Literal::Date(value)) => compare_ordered(&task.date, operator, value),
+        (Field::Status, Literal::Status(value)) => compare_ordered(&task.status, operator, value),
+        (Field::Id, Literal::Number(value)) => compare_ordered(&task.id, operator, value),
+        _ => false,
+    }
+}
+
+fn compare_string(field: &str, operator: Operator, value: &str) -> bool {
+    match operator {
+        Operator::Like => field.contains(value),
+        Operator::NotLike => !field.contains(value),
+        Operator::StartsWith => field.starts_with(value),
+        Operator::EndsWith => field.ends_with(value),
+        _ => compare_ordered(field, operator, value),
+    }
+}
+
+fn compare_ordered<T: Ord + ?Sized>(field: &T, operator: Operator, value: &T) -> bool {
+    match operator {
+        Operator::Lt => field < value,
+        Operator::Le => field <= value,
+        Operator::Eq => field == value,
+        Operator::Ne => field != value,
+        Operator::Ge => field >= value,
+        Operator::Gt => field > value,
+        Operator::Like
+        | Operator::NotLike
+        | Operator::StartsWith
+        | Operator::EndsWith
+        | Operator::In
+        | Operator::Matches => false,
+    }
+}
@@ -0,0 +1,33 @@
+use std::fmt;
+
+/// A syntax or type error in a select query, positioned at the character
+/// (0-based) where the problem was detected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl QueryError {
+    pub fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// Renders the error together with the offending query and a caret
+    /// pointing at the error position.
+    #[must_use]
+    pub fn render(&self, input: &str) -> String {
+        format!("{self}\n  {input}\n  {}^", " ".repeat(self.position))
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for QueryError {}
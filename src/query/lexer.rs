@@ -0,0 +1,208 @@
+use super::error::QueryError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    Star,
+    Symbol(&'static str),
+    Word(String),
+    Str(String),
+    /// A `/source/flags` regular expression literal.
+    Regex { source: String, flags: String },
+    Eof,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub position: usize,
+}
+
+// Longer symbols come first so that `<=` is not lexed as `<` followed by `=`.
+const SYMBOLS: [&str; 10] = ["<=", ">=", "!=", "<>", "<", ">", "=", "(", ")", ","];
+
+fn symbol_at(chars: &[char], i: usize) -> Option<&'static str> {
+    SYMBOLS
+        .iter()
+        .find(|s| s.chars().enumerate().all(|(k, sc)| chars.get(i + k) == Some(&sc)))
+        .copied()
+}
+
+const fn is_quote(c: char) -> bool {
+    matches!(c, '"' | '\'')
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c == '*' {
+            tokens.push(Token { kind: TokenKind::Star, position: start });
+            i += 1;
+            continue;
+        }
+
+        if let Some(symbol) = symbol_at(&chars, i) {
+            tokens.push(Token { kind: TokenKind::Symbol(symbol), position: start });
+            i += symbol.len();
+            continue;
+        }
+
+        if c == '/' && matches!(tokens.last(), Some(Token { kind: TokenKind::Word(w), .. }) if w.eq_ignore_ascii_case("matches")) {
+            let mut source = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(QueryError::new(start, "unterminated regular expression")),
+                    Some('\\') if chars.get(i + 1) == Some(&'/') => {
+                        source.push('/');
+                        i += 2;
+                    }
+                    Some('/') => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&ch) => {
+                        source.push(ch);
+                        i += 1;
+                    }
+                }
+            }
+            let flags_start = i;
+            while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                i += 1;
+            }
+            let flags: String = chars[flags_start..i].iter().collect();
+            tokens.push(Token { kind: TokenKind::Regex { source, flags }, position: start });
+            continue;
+        }
+
+        if is_quote(c) {
+            let quote = c;
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(QueryError::new(start, "unterminated string literal")),
+                    Some('\\') if chars.get(i + 1) == Some(&quote) || chars.get(i + 1) == Some(&'\\') => {
+                        value.push(chars[i + 1]);
+                        i += 2;
+                    }
+                    Some(&ch) if ch == quote => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&ch) => {
+                        value.push(ch);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token { kind: TokenKind::Str(value), position: start });
+            continue;
+        }
+
+        while i < chars.len()
+            && !chars[i].is_whitespace()
+            && !is_quote(chars[i])
+            && symbol_at(&chars, i).is_none()
+        {
+            i += 1;
+        }
+        let word: String = chars[start..i].iter().collect();
+        tokens.push(Token { kind: TokenKind::Word(word), position: start });
+    }
+
+    tokens.push(Token { kind: TokenKind::Eof, position: chars.len() });
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        tokenize(input)
+            .expect("tokenize failed")
+            .into_iter()
+            .map(|t| t.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize_condition_without_spaces() {
+        assert_eq!(
+            kinds("category=work"),
+            vec![
+                TokenKind::Word("category".to_string()),
+                TokenKind::Symbol("="),
+                TokenKind::Word("work".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_quoted_string_with_escape() {
+        assert_eq!(
+            kinds(r#"name = "say \"hi\" and go""#),
+            vec![
+                TokenKind::Word("name".to_string()),
+                TokenKind::Symbol("="),
+                TokenKind::Str("say \"hi\" and go".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_list_and_inequality() {
+        assert_eq!(
+            kinds("name!=a,b<>(c)"),
+            vec![
+                TokenKind::Word("name".to_string()),
+                TokenKind::Symbol("!="),
+                TokenKind::Word("a".to_string()),
+                TokenKind::Symbol(","),
+                TokenKind::Word("b".to_string()),
+                TokenKind::Symbol("<>"),
+                TokenKind::Symbol("("),
+                TokenKind::Word("c".to_string()),
+                TokenKind::Symbol(")"),
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_regex_only_after_matches() {
+        assert_eq!(
+            kinds(r"name matches /^OPS-\d+\/x/im and description = a/b"),
+            vec![
+                TokenKind::Word("name".to_string()),
+                TokenKind::Word("matches".to_string()),
+                TokenKind::Regex { source: r"^OPS-\d+/x".to_string(), flags: "im".to_string() },
+                TokenKind::Word("and".to_string()),
+                TokenKind::Word("description".to_string()),
+                TokenKind::Symbol("="),
+                TokenKind::Word("a/b".to_string()),
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_unterminated_string() {
+        let err = tokenize("name = 'oops").expect_err("should fail");
+        assert_eq!(err.position, 7);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod ast;
+mod error;
+mod lexer;
+mod parser;
+
+pub use error::QueryError;
+pub use parser::parse;
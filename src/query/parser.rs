@@ -0,0 +1,606 @@
+use super::ast::{Condition, Expr, Field, Literal, Operator, Pattern, Query, SortKey};
+use super::error::QueryError;
+use super::lexer::{tokenize, Token, TokenKind};
+use crate::models::status::Status;
+use crate::utils::date;
+
+/// Parses a select query of the form `*`, `* where <predicate>` or a bare
+/// `<predicate>`, optionally followed by ordering and pagination clauses.
+///
+/// ```text
+/// query     := selection [ 'order' 'by' sort_key ( ',' sort_key )* ]
+///              [ 'limit' number ] [ 'offset' number ]
+/// selection := '*' [ 'where' predicate ] | [ predicate ]
+/// sort_key  := field [ 'asc' | 'desc' ]
+/// predicate := and_expr ( 'or' and_expr )*
+/// and_expr  := not_expr ( 'and' not_expr )*
+/// not_expr  := 'not' not_expr | primary
+/// primary   := '(' predicate ')' | 'changed' 'since' value | condition
+/// condition := field operator value | field 'in' '(' value ( ',' value )* ')'
+///            | field 'matches' '/' regex '/' flags
+/// operator  := '<' | '<=' | '=' | '!=' | '<>' | '>=' | '>'
+///            | 'like' | 'not like' | 'starts with' | 'ends with'
+/// value     := string | word+
+/// ```
+///
+/// # Errors
+///
+/// Returns a [`QueryError`] pointing at the offending part of `input`.
+pub fn parse(input: &str) -> Result<Query, QueryError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let query = parser.query()?;
+    parser.expect_eof()?;
+    Ok(query)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn peek_at(&self, offset: usize) -> &Token {
+        let last = self.tokens.len() - 1;
+        &self.tokens[(self.pos + offset).min(last)]
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.peek().kind, TokenKind::Word(w) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.at_keyword(keyword) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, QueryError> {
+        Err(QueryError::new(self.peek().position, message))
+    }
+
+    fn describe(token: &Token) -> String {
+        match &token.kind {
+            TokenKind::Star => "`*`".to_string(),
+            TokenKind::Symbol(s) => format!("`{s}`"),
+            TokenKind::Word(w) => format!("`{w}`"),
+            TokenKind::Str(s) => format!("string \"{s}\""),
+            TokenKind::Regex { source, flags } => format!("regex /{source}/{flags}"),
+            TokenKind::Eof => "end of query".to_string(),
+        }
+    }
+
+    fn expect_eof(&self) -> Result<(), QueryError> {
+        if self.peek().kind == TokenKind::Eof {
+            Ok(())
+        } else {
+            self.error(format!("unexpected {}", Self::describe(self.peek())))
+        }
+    }
+
+    fn query(&mut self) -> Result<Query, QueryError> {
+        let filter = self.selection()?;
+        let order_by = if self.at_clause("order") {
+            self.advance();
+            self.advance();
+            self.order_by()?
+        } else {
+            Vec::new()
+        };
+        let limit = if self.eat_keyword("limit") { Some(self.number()?) } else { None };
+        let offset = if self.eat_keyword("offset") { self.number()? } else { 0 };
+
+        Ok(Query { filter, order_by, limit, offset })
+    }
+
+    fn selection(&mut self) -> Result<Option<Expr>, QueryError> {
+        if self.peek().kind == TokenKind::Star {
+            self.advance();
+            if self.eat_keyword("where") {
+                return Ok(Some(self.predicate()?));
+            }
+            return Ok(None);
+        }
+        if self.peek().kind == TokenKind::Eof
+            || self.at_clause("order")
+            || self.at_keyword("limit")
+            || self.at_keyword("offset")
+        {
+            return Ok(None);
+        }
+        Ok(Some(self.predicate()?))
+    }
+
+    /// Whether the parser is looking at the start of a trailing clause:
+    /// `order by`, `limit <n>` or `offset <n>`.
+    fn at_clause(&self, keyword: &str) -> bool {
+        if !self.at_keyword(keyword) {
+            return false;
+        }
+        match &self.peek_at(1).kind {
+            TokenKind::Word(next) if keyword.eq_ignore_ascii_case("order") => next.eq_ignore_ascii_case("by"),
+            TokenKind::Word(next) => next.parse::<usize>().is_ok(),
+            _ => false,
+        }
+    }
+
+    fn order_by(&mut self) -> Result<Vec<SortKey>, QueryError> {
+        let mut keys = Vec::new();
+        loop {
+            let field = self.field()?;
+            let descending = if self.eat_keyword("desc") {
+                true
+            } else {
+                self.eat_keyword("asc");
+                false
+            };
+            keys.push(SortKey { field, descending });
+
+            if self.peek().kind != TokenKind::Symbol(",") {
+                return Ok(keys);
+            }
+            self.advance();
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, QueryError> {
+        if let TokenKind::Word(word) = &self.peek().kind {
+            if let Ok(number) = word.parse() {
+                self.advance();
+                return Ok(number);
+            }
+        }
+        self.error(format!("expected a non-negative integer, found {}", Self::describe(self.peek())))
+    }
+
+    fn predicate(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and_expr()?;
+        while self.eat_keyword("or") {
+            let right = self.and_expr()?;
+            expr = Expr::Or(Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.not_expr()?;
+        while self.eat_keyword("and") {
+            let right = self.not_expr()?;
+            expr = Expr::And(Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr, QueryError> {
+        if self.eat_keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not_expr()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, QueryError> {
+        if self.peek().kind == TokenKind::Symbol("(") {
+            let open = self.advance();
+            let expr = self.predicate()?;
+            if self.peek().kind != TokenKind::Symbol(")") {
+                return Err(QueryError::new(
+                    self.peek().position,
+                    format!(
+                        "expected `)` to close `(` at column {}, found {}",
+                        open.position + 1,
+                        Self::describe(self.peek())
+                    ),
+                ));
+            }
+            self.advance();
+            return Ok(expr);
+        }
+        if self.at_keyword("changed") && matches!(&self.peek_at(1).kind, TokenKind::Word(w) if w.eq_ignore_ascii_case("since")) {
+            self.advance();
+            self.advance();
+            let position = self.peek().position;
+            let text = self.value()?;
+            return Ok(Expr::ChangedSince(date::parse(&text).map_err(|e| QueryError::new(position, e))?));
+        }
+        Ok(Expr::Condition(self.condition()?))
+    }
+
+    fn condition(&mut self) -> Result<Condition, QueryError> {
+        let field = self.field()?;
+        let operator_token = self.peek().clone();
+        let operator = self.operator()?;
+
+        if !field.supports(operator) {
+            return Err(QueryError::new(
+                operator_token.position,
+                format!("operator `{}` is not supported for field `{}`", operator.as_str(), field.as_str()),
+            ));
+        }
+
+        let value = match operator {
+            Operator::In => self.list(field)?,
+            Operator::Matches => self.pattern()?,
+            _ => self.literal(field)?,
+        };
+
+        Ok(Condition { field, operator, value })
+    }
+
+    fn list(&mut self, field: Field) -> Result<Literal, QueryError> {
+        if self.peek().kind != TokenKind::Symbol("(") {
+            return self.error(format!("expected `(` after `in`, found {}", Self::describe(self.peek())));
+        }
+        self.advance();
+
+        let mut items = Vec::new();
+        loop {
+            match self.literal(field)? {
+                Literal::List(aliased) => items.extend(aliased),
+                item => items.push(item),
+            }
+            if self.peek().kind != TokenKind::Symbol(",") {
+                break;
+            }
+            self.advance();
+        }
+
+        if self.peek().kind != TokenKind::Symbol(")") {
+            return self.error(format!("expected `,` or `)` in list, found {}", Self::describe(self.peek())));
+        }
+        self.advance();
+        Ok(Literal::List(items))
+    }
+
+    fn pattern(&mut self) -> Result<Literal, QueryError> {
+        let token = self.peek().clone();
+        let TokenKind::Regex { source, flags } = &token.kind else {
+            return self.error(format!("expected /regex/ after `matches`, found {}", Self::describe(&token)));
+        };
+
+        if let Some(flag) = flags.chars().find(|f| !matches!(f, 'i' | 'm' | 's')) {
+            let offset = source.chars().count() + 2 + flags.chars().position(|f| f == flag).unwrap_or(0);
+            return Err(QueryError::new(
+                token.position + offset,
+                format!("unknown regex flag `{flag}`, expected i, m or s"),
+            ));
+        }
+
+        let pattern = Pattern::new(source, flags)
+            .map_err(|e| {
+                // The regex crate renders its own multi-line caret diagram;
+                // keep only the final summary line.
+                let message = e.to_string();
+                let summary = message.lines().last().unwrap_or_default().trim_start_matches("error: ");
+                QueryError::new(token.position, format!("invalid regex: {summary}"))
+            })?;
+        self.advance();
+        Ok(Literal::Pattern(pattern))
+    }
+
+    fn literal(&mut self, field: Field) -> Result<Literal, QueryError> {
+        let position = self.peek().position;
+        let text = self.value()?;
+        Ok(match field {
+            Field::Date => Literal::Date(
+                date::parse(&text).map_err(|e| QueryError::new(position, e))?,
+            ),
+            Field::Id => Literal::Number(
+                text.trim_start_matches('#').parse().map_err(|_| {
+                    QueryError::new(position, format!("invalid task ID `{text}`"))
+                })?,
+            ),
+            Field::Status => match Status::parse_filter(&text).map_err(|e| QueryError::new(position, e))?.as_slice() {
+                [status] => Literal::Status(*status),
+                statuses => Literal::List(statuses.iter().copied().map(Literal::Status).collect()),
+            },
+            Field::Name | Field::Description | Field::Category => Literal::Text(text),
+        })
+    }
+
+    fn field(&mut self) -> Result<Field, QueryError> {
+        let token = self.peek().clone();
+        if let TokenKind::Word(word) = &token.kind {
+            if let Some(field) = Field::from_keyword(word) {
+                self.advance();
+                return Ok(field);
+            }
+            return self.error(format!("unknown field `{word}`"));
+        }
+        self.error(format!("expected field name, found {}", Self::describe(&token)))
+    }
+
+    fn operator(&mut self) -> Result<Operator, QueryError> {
+        let (operator, length) = match (&self.peek().kind, &self.peek_at(1).kind) {
+            (TokenKind::Symbol("<"), _) => (Operator::Lt, 1),
+            (TokenKind::Symbol("<="), _) => (Operator::Le, 1),
+            (TokenKind::Symbol("="), _) => (Operator::Eq, 1),
+            (TokenKind::Symbol("!=" | "<>"), _) => (Operator::Ne, 1),
+            (TokenKind::Symbol(">="), _) => (Operator::Ge, 1),
+            (TokenKind::Symbol(">"), _) => (Operator::Gt, 1),
+            (TokenKind::Word(w), _) if w.eq_ignore_ascii_case("like") => (Operator::Like, 1),
+            (TokenKind::Word(w), _) if w.eq_ignore_ascii_case("in") => (Operator::In, 1),
+            (TokenKind::Word(w), _) if w.eq_ignore_ascii_case("matches") => (Operator::Matches, 1),
+            (TokenKind::Word(w), TokenKind::Word(next))
+                if w.eq_ignore_ascii_case("not") && next.eq_ignore_ascii_case("like") =>
+            {
+                (Operator::NotLike, 2)
+            }
+            (TokenKind::Word(w), TokenKind::Word(next))
+                if w.eq_ignore_ascii_case("starts") && next.eq_ignore_ascii_case("with") =>
+            {
+                (Operator::StartsWith, 2)
+            }
+            (TokenKind::Word(w), TokenKind::Word(next))
+                if w.eq_ignore_ascii_case("ends") && next.eq_ignore_ascii_case("with") =>
+            {
+                (Operator::EndsWith, 2)
+            }
+            _ => {
+                return self.error(format!("expected operator, found {}", Self::describe(self.peek())));
+            }
+        };
+        for _ in 0..length {
+            self.advance();
+        }
+        Ok(operator)
+    }
+
+    /// A value is either a single quoted string or a run of bare words up to
+    /// the next keyword.
+    fn value(&mut self) -> Result<String, QueryError> {
+        if let TokenKind::Str(s) = &self.peek().kind {
+            let s = s.clone();
+            self.advance();
+            return Ok(s);
+        }
+
+        let mut words = Vec::new();
+        while let TokenKind::Word(word) = &self.peek().kind {
+            if word.eq_ignore_ascii_case("and")
+                || word.eq_ignore_ascii_case("or")
+                || self.at_clause("order")
+                || self.at_clause("limit")
+                || self.at_clause("offset")
+            {
+                break;
+            }
+            words.push(word.clone());
+            self.advance();
+        }
+
+        if words.is_empty() {
+            return self.error(format!("expected value, found {}", Self::describe(self.peek())));
+        }
+        Ok(words.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::status::Status;
+
+    #[test]
+    fn test_parse_star_selects_everything() {
+        assert_eq!(parse("*"), Ok(Query::default()));
+        assert_eq!(parse(""), Ok(Query::default()));
+    }
+
+    #[test]
+    fn test_parse_values_with_spaces_and_keywords() {
+        let query = parse("* where name = \"this and that\" and category = Category A")
+            .expect("query should parse");
+        let expected = Expr::And(
+            Box::new(Expr::Condition(Condition {
+                field: Field::Name,
+                operator: Operator::Eq,
+                value: Literal::Text("this and that".to_string()),
+            })),
+            Box::new(Expr::Condition(Condition {
+                field: Field::Category,
+                operator: Operator::Eq,
+                value: Literal::Text("Category A".to_string()),
+            })),
+        );
+        assert_eq!(query.filter, Some(expected));
+    }
+
+    #[test]
+    fn test_parse_date_literal() {
+        let query = parse("date < 31-12-2023 00:00").expect("query should parse");
+        let expected = date::parse("31-12-2023 00:00").expect("valid date");
+        assert!(matches!(
+            query.filter,
+            Some(Expr::Condition(Condition { value: Literal::Date(d), .. })) if d == expected
+        ));
+    }
+
+    fn condition(field: Field, value: &str) -> Expr {
+        Expr::Condition(Condition {
+            field,
+            operator: Operator::Eq,
+            value: Literal::Text(value.to_string()),
+        })
+    }
+
+    #[test]
+    fn test_parse_and_binds_tighter_than_or() {
+        let query = parse("name = a or name = b and category = c").expect("query should parse");
+        let expected = Expr::Or(
+            Box::new(condition(Field::Name, "a")),
+            Box::new(Expr::And(
+                Box::new(condition(Field::Name, "b")),
+                Box::new(condition(Field::Category, "c")),
+            )),
+        );
+        assert_eq!(query.filter, Some(expected));
+    }
+
+    #[test]
+    fn test_parse_grouping_and_negation() {
+        let query = parse("(category = work or category = ops) and not name = draft")
+            .expect("query should parse");
+        let expected = Expr::And(
+            Box::new(Expr::Or(
+                Box::new(condition(Field::Category, "work")),
+                Box::new(condition(Field::Category, "ops")),
+            )),
+            Box::new(Expr::Not(Box::new(condition(Field::Name, "draft")))),
+        );
+        assert_eq!(query.filter, Some(expected));
+    }
+
+    #[test]
+    fn test_parse_changed_since() {
+        let query = parse("changed since '01-03-2024 00:00' and category = work").expect("query should parse");
+        let since = date::parse("01-03-2024 00:00").expect("valid date");
+        let expected = Expr::And(
+            Box::new(Expr::ChangedSince(since)),
+            Box::new(condition(Field::Category, "work")),
+        );
+        assert_eq!(query.filter, Some(expected));
+
+        let mut filter = query.filter.expect("query has a filter");
+        assert!(filter.mentions_changes());
+        filter.resolve_changes(&|_| [2, 5].into());
+        assert!(!filter.mentions_changes());
+        let Expr::And(resolved, _) = filter else { panic!("expected and") };
+        assert_eq!(*resolved, Expr::Condition(Condition {
+            field: Field::Id,
+            operator: Operator::In,
+            value: Literal::List(vec![Literal::Number(2), Literal::Number(5)]),
+        }));
+
+        assert!(parse("changed since").is_err());
+    }
+
+    #[test]
+    fn test_parse_status_aliases() {
+        for (input, expected) in [
+            ("done", Status::Done),
+            ("ON", Status::Done),
+            ("pending", Status::Pending),
+            ("in_progress", Status::InProgress),
+            ("cancelled", Status::Cancelled),
+        ] {
+            let query = parse(&format!("status = {input}")).expect("query should parse");
+            assert_eq!(
+                query.filter,
+                Some(Expr::Condition(Condition {
+                    field: Field::Status,
+                    operator: Operator::Eq,
+                    value: Literal::Status(expected),
+                }))
+            );
+        }
+
+        let open = Literal::List(vec![
+            Literal::Status(Status::Pending),
+            Literal::Status(Status::InProgress),
+            Literal::Status(Status::Blocked),
+            Literal::Status(Status::Waiting),
+        ]);
+        for input in ["status = off", "status = False", "status = open"] {
+            let query = parse(input).expect("query should parse");
+            assert_eq!(
+                query.filter,
+                Some(Expr::Condition(Condition { field: Field::Status, operator: Operator::Eq, value: open.clone() })),
+                "{input}"
+            );
+        }
+        let query = parse("status in (open, cancelled)").expect("query should parse");
+        let Some(Expr::Condition(Condition { value: Literal::List(items), .. })) = query.filter else {
+            panic!("expected a list condition");
+        };
+        assert_eq!(items.len(), 5);
+    }
+
+    #[test]
+    fn test_parse_multi_word_operators() {
+        for (input, expected) in [
+            ("name != a", Operator::Ne),
+            ("name <> a", Operator::Ne),
+            ("name not like a", Operator::NotLike),
+            ("name starts with a", Operator::StartsWith),
+            ("name ends with a", Operator::EndsWith),
+            ("name >= a", Operator::Ge),
+        ] {
+            let query = parse(input).expect("query should parse");
+            assert!(
+                matches!(query.filter, Some(Expr::Condition(Condition { operator, .. })) if operator == expected),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_in_list() {
+        let query = parse("category in (work, \"side project\", Category A)").expect("query should parse");
+        assert_eq!(
+            query.filter,
+            Some(Expr::Condition(Condition {
+                field: Field::Category,
+                operator: Operator::In,
+                value: Literal::List(vec![
+                    Literal::Text("work".to_string()),
+                    Literal::Text("side project".to_string()),
+                    Literal::Text("Category A".to_string()),
+                ]),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_order_limit_offset() {
+        let query = parse("* where category = side project order by status, date desc limit 10 offset 20")
+            .expect("query should parse");
+        assert_eq!(query.filter, Some(condition(Field::Category, "side project")));
+        assert_eq!(
+            query.order_by,
+            vec![
+                SortKey { field: Field::Status, descending: false },
+                SortKey { field: Field::Date, descending: true },
+            ]
+        );
+        assert_eq!(query.limit, Some(10));
+        assert_eq!(query.offset, 20);
+
+        let query = parse("* order by name asc limit 5").expect("query should parse");
+        assert_eq!(query.filter, None);
+        assert_eq!(query.limit, Some(5));
+    }
+
+    #[test]
+    fn test_parse_errors_are_positioned() {
+        assert_eq!(parse("nme = x").expect_err("unknown field").position, 0);
+        assert_eq!(parse("name x").expect_err("missing operator").position, 5);
+        assert_eq!(parse("name = x and").expect_err("dangling and").position, 12);
+        assert_eq!(parse("status < done").expect_err("bad operator").position, 7);
+        assert_eq!(parse("date like x").expect_err("bad operator").position, 5);
+        assert_eq!(parse("name in (a, b").expect_err("unclosed list").position, 13);
+        assert_eq!(parse("name matches /a/ix").expect_err("bad flag").position, 17);
+        assert_eq!(parse("* limit x").expect_err("bad limit").position, 8);
+        assert_eq!(parse("* order by size").expect_err("bad sort field").position, 11);
+        assert_eq!(parse("name matches /(/").expect_err("bad regex").position, 13);
+        assert_eq!(parse("date matches /1/").expect_err("regex on date").position, 5);
+        assert_eq!(parse("date = someday").expect_err("bad date").position, 7);
+        assert_eq!(parse("status = maybe").expect_err("bad status").position, 9);
+        assert_eq!(parse("(name = x").expect_err("unclosed group").position, 9);
+        assert_eq!(parse("name = x)").expect_err("stray paren").position, 8);
+    }
+}
@@ -0,0 +1,199 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::error::TodoError;
+use crate::models::task::Task;
+use crate::utils::datetime_format;
+use super::journal::show;
+
+/// One field of a task changed by one command. `old` is `None` for tasks
+/// that were just added and `new` for tasks removed for good.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub task: u64,
+    #[serde(with = "datetime_format")]
+    pub at: DateTime<Utc>,
+    pub user: String,
+    /// The command that made the change, e.g. `update` or `undo delete`.
+    pub action: String,
+    pub field: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl Change {
+    /// A change for every field that differs between `before` and `after`,
+    /// where `None` means the task did not exist.
+    #[must_use]
+    pub fn between(before: Option<&Task>, after: Option<&Task>, action: &str, at: DateTime<Utc>, user: &str) -> Vec<Self> {
+        let Some(existing) = before.or(after) else {
+            return Vec::new();
+        };
+        let task = existing.id;
+        let values = |task: Option<&Task>| task.map_or_else(Default::default, |task| task.fields().map(|(_, value)| value));
+        existing.fields()
+            .map(|(field, _)| field)
+            .into_iter()
+            .zip(values(before).into_iter().zip(values(after)))
+            .filter(|(_, (old, new))| old != new)
+            .map(|(field, (old, new))| Self {
+                task,
+                at,
+                user: user.to_string(),
+                action: action.to_string(),
+                field: field.to_string(),
+                old,
+                new,
+            })
+            .collect()
+    }
+
+    /// The change in the form `field "old" -> "new"`.
+    #[must_use]
+    pub fn describe(&self) -> String {
+        format!("{} {} -> {}", self.field, show(self.old.as_ref()), show(self.new.as_ref()))
+    }
+}
+
+/// The change history: appended to a JSON Lines file, or kept in memory
+/// for services without one.
+#[derive(Debug, Default)]
+pub struct History {
+    path: Option<PathBuf>,
+    changes: Vec<Change>,
+}
+
+impl History {
+    /// Where the history for the store at `path` is kept: `<path>.history`.
+    #[must_use]
+    pub fn path_for(path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".history");
+        path.with_file_name(name)
+    }
+
+    #[must_use]
+    pub const fn in_file(path: PathBuf) -> Self {
+        Self {
+            path: Some(path),
+            changes: Vec::new(),
+        }
+    }
+
+    /// Records `changes`. The file is only ever appended to.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the file cannot be written.
+    pub fn append(&mut self, changes: Vec<Change>) -> Result<(), TodoError> {
+        let Some(path) = &self.path else {
+            self.changes.extend(changes);
+            return Ok(());
+        };
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        // A line cut short by a crash is closed off, so it costs one entry
+        // rather than the ones that follow it.
+        let mut lines = if ends_mid_line(&mut file)? { "\n".to_string() } else { String::new() };
+        for change in &changes {
+            lines.push_str(&serde_json::to_string(change)?);
+            lines.push('\n');
+        }
+        file.write_all(lines.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Every recorded change, oldest first. Unreadable lines, such as one
+    /// cut short by a crash, are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the file cannot be read.
+    pub fn changes(&self) -> Result<Vec<Change>, TodoError> {
+        let Some(path) = &self.path else {
+            return Ok(self.changes.clone());
+        };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(contents.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+    }
+}
+
+fn ends_mid_line(file: &mut File) -> io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(false);
+    }
+    file.seek(SeekFrom::End(-1))?;
+    let mut last = [0];
+    file.read_exact(&mut last)?;
+    Ok(last[0] != b'\n')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use crate::models::status::Status;
+
+    fn task() -> Task {
+        Task {
+            id: 7,
+            name: "Report".to_string(),
+            description: String::new(),
+            date: Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).single().expect("valid date"),
+            category: "work".to_string(),
+            status: Status::Pending,
+            deleted: None,
+            reopened: None,
+        }
+    }
+
+    #[test]
+    fn test_between_lists_changed_fields() {
+        let at = Utc::now();
+        let before = task();
+        let after = Task { status: Status::Done, date: before.date + chrono::Duration::days(1), ..before.clone() };
+
+        let changes = Change::between(Some(&before), Some(&after), "update", at, "alice");
+        let fields: Vec<(&str, Option<&str>, Option<&str>)> = changes.iter()
+            .map(|change| (change.field.as_str(), change.old.as_deref(), change.new.as_deref()))
+            .collect();
+        assert_eq!(fields, [
+            ("date", Some("2024-03-01T09:00:00Z"), Some("2024-03-02T09:00:00Z")),
+            ("status", Some("pending"), Some("done")),
+        ]);
+        assert!(changes.iter().all(|change| change.task == 7 && change.user == "alice"));
+
+        let added = Change::between(None, Some(&before), "add", at, "alice");
+        assert_eq!(added.len(), 5);
+        assert!(added.iter().all(|change| change.old.is_none()));
+    }
+
+    #[test]
+    fn test_file_is_appended_to_and_survives_a_cut_line() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("tasks.json.history");
+        let before = task();
+        let after = Task { name: "Renamed".to_string(), ..before.clone() };
+
+        let mut history = History::in_file(path.clone());
+        history.append(Change::between(None, Some(&before), "add", Utc::now(), "alice")).expect("append");
+        let mut file = OpenOptions::new().append(true).open(&path).expect("open history");
+        file.write_all(b"{\"task\":7,\"at\":").expect("write partial line");
+        history.append(Change::between(Some(&before), Some(&after), "update", Utc::now(), "bob")).expect("append");
+
+        let changes = history.changes().expect("read history");
+        assert_eq!(changes.len(), 6);
+        assert_eq!(changes[5].new.as_deref(), Some("Renamed"));
+        assert_eq!(changes[5].user, "bob");
+    }
+}
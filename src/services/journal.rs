@@ -0,0 +1,152 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::error::TodoError;
+use crate::models::task::Task;
+use crate::utils::{atomic_file, datetime_format};
+
+/// How many operations the journal keeps for `undo`.
+pub const JOURNAL_LIMIT: usize = 100;
+
+/// The command that made an [`Operation`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationKind {
+    Add,
+    Done,
+    Start,
+    Block,
+    Wait,
+    Cancel,
+    Reopen,
+    Update,
+    Delete,
+    Restore,
+    Purge,
+}
+
+impl fmt::Display for OperationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Add => "add",
+            Self::Done => "done",
+            Self::Start => "start",
+            Self::Block => "block",
+            Self::Wait => "wait",
+            Self::Cancel => "cancel",
+            Self::Reopen => "reopen",
+            Self::Update => "update",
+            Self::Delete => "delete",
+            Self::Restore => "restore",
+            Self::Purge => "purge",
+        })
+    }
+}
+
+/// One change to a task, kept with the task as it was before and after so
+/// it can be reverted and reapplied. `None` means the task did not exist.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub kind: OperationKind,
+    #[serde(with = "datetime_format")]
+    pub at: DateTime<Utc>,
+    pub before: Option<Task>,
+    pub after: Option<Task>,
+}
+
+impl Operation {
+    /// The task the operation changed.
+    #[must_use]
+    pub fn id(&self) -> u64 {
+        self.before.as_ref().or(self.after.as_ref()).map_or(0, |task| task.id)
+    }
+
+    /// What undoing (or, with `undo` unset, redoing) the operation does,
+    /// e.g. `restore #3 "Report"` or `change #3 "Report": status "done" -> "pending"`.
+    #[must_use]
+    pub fn describe(&self, undo: bool) -> String {
+        let (from, to) = if undo { (&self.after, &self.before) } else { (&self.before, &self.after) };
+        match (from, to) {
+            (Some(task), None) => format!("remove #{} \"{}\"", task.id, task.name),
+            (None, Some(task)) if task.is_trashed() => format!("restore #{} \"{}\" into the trash", task.id, task.name),
+            (None, Some(task)) => format!("restore #{} \"{}\"", task.id, task.name),
+            (Some(old), Some(new)) if !old.is_trashed() && new.is_trashed() => {
+                format!("move #{} \"{}\" to the trash", old.id, old.name)
+            }
+            (Some(old), Some(new)) if old.is_trashed() && !new.is_trashed() => {
+                format!("restore #{} \"{}\" from the trash", old.id, old.name)
+            }
+            (Some(old), Some(new)) => format!("change #{} \"{}\": {}", old.id, old.name, differences(old, new).join(", ")),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// The fields that differ between `old` and `new`, as `field old -> new`.
+fn differences(old: &Task, new: &Task) -> Vec<String> {
+    old.fields()
+        .into_iter()
+        .zip(new.fields())
+        .filter(|((_, old), (_, new))| old != new)
+        .map(|((field, old), (_, new))| format!("{field} {} -> {}", show(old.as_ref()), show(new.as_ref())))
+        .collect()
+}
+
+/// A field value for display: quoted, or `none` if it is unset.
+pub(super) fn show(value: Option<&String>) -> String {
+    value.map_or_else(|| "none".to_string(), |value| format!("{value:?}"))
+}
+
+/// The operations that can be undone and redone, newest last. Making a new
+/// change clears the redo side.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Journal {
+    pub undo: Vec<Operation>,
+    pub redo: Vec<Operation>,
+}
+
+impl Journal {
+    /// Where the journal for the store at `path` is kept: `<path>.journal`.
+    #[must_use]
+    pub fn path_for(path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".journal");
+        path.with_file_name(name)
+    }
+
+    /// Reads the journal at `path`; a missing file is an empty journal.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the file cannot be read, or
+    /// [`TodoError::Corrupt`] if it is not a journal.
+    pub fn load(path: &Path) -> Result<Self, TodoError> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the journal to `path` (see [`atomic_file::write`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the file cannot be written.
+    pub fn save(&self, path: &Path) -> Result<(), TodoError> {
+        atomic_file::write(path, serde_json::to_string_pretty(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    /// Adds a new operation, dropping the oldest beyond [`JOURNAL_LIMIT`].
+    pub fn record(&mut self, operation: Operation) {
+        self.undo.push(operation);
+        if self.undo.len() > JOURNAL_LIMIT {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+}
@@ -1,2 +1,6 @@
+mod history;
+mod journal;
 mod todo_list_service;
-pub use todo_list_service::TodoListService;
+pub use history::{Change, History};
+pub use journal::{Journal, Operation, OperationKind, JOURNAL_LIMIT};
+pub use todo_list_service::{TodoListService, UNKNOWN_USER};
@@ -1,285 +1,697 @@
-use std::collections::HashMap;
-use std::fs::OpenOptions;
-use std::io::{self, Write, Read};
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::PathBuf;
-use std::env;
-use chrono::{DateTime, Utc, NaiveDateTime};
-use serde::{Deserialize, Serialize};
-use crate::models::task::Task;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use crate::error::TodoError;
+use crate::models::status::{Status, Transitions};
+use crate::models::task::{Task, TaskChanges};
+use crate::query;
 use crate::utils::date;
+use super::history::{Change, History};
+use super::journal::{Journal, Operation, OperationKind};
+use crate::storage::{JsonFileStore, MemoryStore, TaskList, TaskStore, DEFAULT_LOCK_TIMEOUT};
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Debug)]
 pub struct TodoListService {
-    tasks: HashMap<String, Task>,
+    list: TaskList,
+    store: Box<dyn TaskStore>,
+    journal: Journal,
+    journal_path: Option<PathBuf>,
+    /// Why the journal file could not be read, until it is replaced.
+    journal_damage: Option<String>,
+    history: History,
+    user: String,
+    transitions: Transitions,
 }
 
+/// Who changes are attributed to until [`TodoListService::with_user`] says
+/// otherwise.
+pub const UNKNOWN_USER: &str = "unknown";
+
 impl TodoListService {
+    /// Creates an empty task list backed by a [`MemoryStore`].
+    #[must_use]
     pub fn new() -> Self {
         Self {
-            tasks: HashMap::new(),
+            list: TaskList::default(),
+            store: Box::<MemoryStore>::default(),
+            journal: Journal::default(),
+            journal_path: None,
+            journal_damage: None,
+            history: History::default(),
+            user: UNKNOWN_USER.to_string(),
+            transitions: Transitions::default(),
         }
     }
 
-    pub fn add_task(&mut self, name: String, description: String, date: DateTime<Utc>, category: String) {
+    /// Loads the task list from `store`; every later change is written back
+    /// to it.
+    ///
+    /// # Errors
+    ///
+    /// Whatever [`TaskStore::load`] returns.
+    pub fn with_store(mut store: Box<dyn TaskStore>) -> Result<Self, TodoError> {
+        let list = store.load()?;
+        Ok(Self {
+            list,
+            store,
+            journal: Journal::default(),
+            journal_path: None,
+            journal_damage: None,
+            history: History::default(),
+            user: UNKNOWN_USER.to_string(),
+            transitions: Transitions::default(),
+        })
+    }
+
+    /// Appends the change history to the file at `path` instead of keeping
+    /// it in memory.
+    #[must_use]
+    pub fn with_history(mut self, path: impl Into<PathBuf>) -> Self {
+        self.history = History::in_file(path.into());
+        self
+    }
+
+    /// Attributes later changes to `user` in the history.
+    #[must_use]
+    pub fn with_user(mut self, user: impl Into<String>) -> Self {
+        self.user = user.into();
+        self
+    }
+
+    /// Restricts status changes to `transitions` instead of
+    /// [`Transitions::default`].
+    #[must_use]
+    pub fn with_transitions(mut self, transitions: Transitions) -> Self {
+        self.transitions = transitions;
+        self
+    }
+
+    /// Keeps the undo journal in the file at `path`, so that changes can
+    /// still be undone by a later process. Without a journal file, only the
+    /// changes made through this service can be undone.
+    ///
+    /// A damaged journal only stops [`undo`](Self::undo) and
+    /// [`redo`](Self::redo) (see [`check_journal`](Self::check_journal)).
+    /// The next change starts a new one, after copying the damaged file
+    /// aside like [`JsonFileStore::preserve_corrupt`] does.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the file cannot be read.
+    pub fn with_journal(mut self, path: impl Into<PathBuf>) -> Result<Self, TodoError> {
+        let path = path.into();
+        self.journal = match Journal::load(&path) {
+            Err(TodoError::Corrupt(e)) => {
+                self.journal_damage = Some(e.to_string());
+                Journal::default()
+            }
+            journal => journal?,
+        };
+        self.journal_path = Some(path);
+        Ok(self)
+    }
+
+    /// Loads the task list from the JSON file at `path` (see
+    /// [`JsonFileStore`]), waiting up to [`DEFAULT_LOCK_TIMEOUT`] for other
+    /// processes to release it. The undo journal and the change history are
+    /// kept next to it (see [`Journal::path_for`] and [`History::path_for`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Locked`] if another process holds the file,
+    /// [`TodoError::Storage`] if it cannot be read or upgraded and
+    /// [`TodoError::Corrupt`] if it is not a valid task list (see
+    /// [`JsonFileStore::repair`]).
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, TodoError> {
+        Self::open_with_timeout(path, DEFAULT_LOCK_TIMEOUT)
+    }
+
+    /// Like [`open`](Self::open), waiting up to `timeout` for the lock.
+    ///
+    /// # Errors
+    ///
+    /// See [`open`](Self::open).
+    pub fn open_with_timeout(path: impl Into<PathBuf>, timeout: Duration) -> Result<Self, TodoError> {
+        let path = path.into();
+        let journal = Journal::path_for(&path);
+        let history = History::path_for(&path);
+        Ok(Self::with_store(Box::new(JsonFileStore::open(path, timeout)?))?
+            .with_journal(journal)?
+            .with_history(history))
+    }
+
+    /// All tasks that are not in the trash, in ID order.
+    pub fn tasks(&self) -> impl Iterator<Item = &Task> {
+        self.list.tasks.values().filter(|task| !task.is_trashed())
+    }
+
+    /// The tasks in the trash, in ID order.
+    pub fn trash(&self) -> impl Iterator<Item = &Task> {
+        self.list.tasks.values().filter(|task| task.is_trashed())
+    }
+
+    #[must_use]
+    pub fn get(&self, id: u64) -> Option<&Task> {
+        self.list.tasks.get(&id)
+    }
+
+    /// Adds a task under a fresh ID. A task whose name is already taken (by
+    /// a task outside the trash) is refused unless `force` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Duplicate`] for a taken name, or
+    /// [`TodoError::Storage`] if the change cannot be saved.
+    pub fn add_task(&mut self, name: String, description: String, date: DateTime<Utc>, category: String, force: bool) -> Result<&Task, TodoError> {
+        if !force {
+            if let Some(existing) = self.tasks().find(|task| task.name == name) {
+                return Err(TodoError::Duplicate { name, id: existing.id });
+            }
+        }
+
+        let id = self.list.next_id.max(self.list.tasks.keys().max().map_or(1, |max| max + 1));
+        self.list.next_id = id + 1;
         let task = Task {
-            name: name.clone(),
+            id,
+            name,
             description,
             date,
             category,
-            status: false,
+            status: Status::Pending,
+            deleted: None,
+            reopened: None,
         };
-        self.tasks.insert(name, task);
-        if let Err(e) = self.save_to_file() {
-            eprintln!("Error saving changes: {e}");
-        }
-        println!("Task added successfully!");
+        self.list.tasks.insert(id, task);
+        self.commit(id)?;
+        self.record(OperationKind::Add, None, id)?;
+        self.lookup(id)
+    }
+
+    /// Resolves a task reference: a numeric ID (optionally prefixed with `#`)
+    /// or, failing that, a name shared by exactly one task. Tasks in the
+    /// trash are not found.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::NotFound`] or, for a name shared by several tasks,
+    /// [`TodoError::Ambiguous`].
+    pub fn find_task(&self, key: &str) -> Result<&Task, TodoError> {
+        self.find_in(key, false)
     }
 
-    pub fn mark_done(&mut self, name: &str) {
-        if let Some(task) = self.tasks.get_mut(name) {
-            task.status = true;
-            if let Err(e) = self.save_to_file() {
-                eprintln!("Error saving changes: {e}");
+    /// Like [`find_task`](Self::find_task), among the tasks in the trash.
+    ///
+    /// # Errors
+    ///
+    /// See [`find_task`](Self::find_task).
+    pub fn find_trashed(&self, key: &str) -> Result<&Task, TodoError> {
+        self.find_in(key, true)
+    }
+
+    fn find_in(&self, key: &str, trashed: bool) -> Result<&Task, TodoError> {
+        let candidates = || self.list.tasks.values().filter(move |task| task.is_trashed() == trashed);
+        if let Ok(id) = key.trim_start_matches('#').parse::<u64>() {
+            if let Some(task) = candidates().find(|task| task.id == id) {
+                return Ok(task);
             }
-            println!("Task marked as done!");
-        } else {
-            println!("Task not found!");
+        }
+
+        let matching: Vec<&Task> = candidates()
+            .filter(|task| task.name == key)
+            .collect();
+        match matching.as_slice() {
+            [task] => Ok(task),
+            [] => Err(TodoError::NotFound(key.to_string())),
+            tasks => Err(TodoError::Ambiguous {
+                name: key.to_string(),
+                ids: tasks.iter().map(|task| task.id).collect(),
+            }),
         }
     }
 
-    pub fn update_task(&mut self, name: &str) {
-        if let Some(task) = self.tasks.get(name) {
-            println!("Enter new details (press Enter to keep current value):");
-            
-            print!("New name ({}): ", task.name);
-            io::stdout().flush().expect("Failed to flush stdout");
-
-            let mut new_name = String::new();
-            io::stdin().read_line(&mut new_name).expect("Failed to read line from stdin");
-            new_name = new_name.trim().to_string();
-            
-            print!("New description ({}): ", task.description);
-            io::stdout().flush().expect("Failed to flush stdout");
-            let mut new_description = String::new();
-            io::stdin().read_line(&mut new_description).expect("Failed to read line from stdin");
-            new_description = new_description.trim().to_string();
-
-            print!("New date in format <'DD-MM-YYYY HH:MM'>({}): ", task.date.to_rfc2822());
-            io::stdout().flush().expect("Failed to flush stdout");
-            let mut new_date_string = String::new();
-            io::stdin().read_line(&mut new_date_string).expect("Failed to read line from stdin");
-            new_date_string = new_date_string.trim().to_string();
-
-            let new_date = match date::parse(new_date_string.as_str()) {
-                Ok(parsed_date) => parsed_date,
-                Err(e) => {
-                    eprintln!("Error parsing date: {e}");
-                    task.date
-                }
-            };
-            
-            print!("New category ({}): ", task.category);
-            io::stdout().flush().expect("Failed to flush stdout");
-            let mut new_category = String::new();
-            io::stdin().read_line(&mut new_category).expect("Failed to read line from stdin");
-            new_category = new_category.trim().to_string();
-            
-            let mut updated_task = task.clone();
-            if !new_name.is_empty() && new_name != task.name {
-                updated_task.name.clone_from(&new_name);
-            }
-            if !new_description.is_empty() {
-                updated_task.description = new_description;
-            }
-            if new_date != task.date {
-                updated_task.date = new_date;
-            }
-            if !new_category.is_empty() {
-                updated_task.category = new_category;
-            }
+    /// # Errors
+    ///
+    /// See [`set_status`](Self::set_status).
+    pub fn mark_done(&mut self, key: &str) -> Result<&Task, TodoError> {
+        self.set_status(key, Status::Done)
+    }
 
-            if updated_task.name == task.name {
-                self.tasks.insert(name.to_string(), updated_task);
-            } else {
-                self.tasks.remove(name);
-                self.tasks.insert(updated_task.name.clone(), updated_task);
-            }
+    /// Moves a task to `status`, which the configured transitions (see
+    /// [`with_transitions`](Self::with_transitions)) must allow.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Transition`] for a change that is not allowed;
+    /// otherwise see [`find_task`](Self::find_task). Also fails if the change
+    /// cannot be saved.
+    pub fn set_status(&mut self, key: &str, status: Status) -> Result<&Task, TodoError> {
+        let kind = match status {
+            Status::Pending => OperationKind::Reopen,
+            Status::InProgress => OperationKind::Start,
+            Status::Blocked => OperationKind::Block,
+            Status::Waiting => OperationKind::Wait,
+            Status::Done => OperationKind::Done,
+            Status::Cancelled => OperationKind::Cancel,
+        };
+        let changes = TaskChanges {
+            status: Some(status),
+            ..TaskChanges::default()
+        };
+        self.change(key, changes, kind)
+    }
 
-            if let Err(e) = self.save_to_file() {
-                eprintln!("Error saving changes: {e}");
-            }
-            println!("Task updated successfully!");
-        } else {
-            println!("Task not found!");
+    /// Sets a done or cancelled task back to pending. When that happened is
+    /// kept in [`Task::reopened`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Conflict`] if the task is neither done nor
+    /// cancelled; otherwise see [`set_status`](Self::set_status).
+    pub fn reopen_task(&mut self, key: &str) -> Result<&Task, TodoError> {
+        let task = self.find_task(key)?;
+        if !task.status.is_closed() {
+            return Err(TodoError::Conflict(format!(
+                "task #{} is {}; only done or cancelled tasks can be reopened",
+                task.id, task.status
+            )));
         }
+        self.set_status(key, Status::Pending)
+    }
+
+    /// Updates only the given fields of a task. Empty `changes` leave the
+    /// task (and the store) untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Transition`] for a status change that is not
+    /// allowed; otherwise see [`find_task`](Self::find_task). Also fails if
+    /// the change cannot be saved.
+    pub fn update_task(&mut self, key: &str, changes: TaskChanges) -> Result<&Task, TodoError> {
+        self.change(key, changes, OperationKind::Update)
+    }
+
+    /// Moves a task to the trash and returns it. It can be brought back
+    /// with [`restore_task`](Self::restore_task) until the trash is emptied.
+    ///
+    /// # Errors
+    ///
+    /// See [`find_task`](Self::find_task); also fails if the change cannot
+    /// be saved.
+    pub fn delete_task(&mut self, key: &str) -> Result<Task, TodoError> {
+        let id = self.find_task(key)?.id;
+        self.set_deleted(id, Some(date::now()), OperationKind::Delete).cloned()
+    }
+
+    /// Takes a task out of the trash.
+    ///
+    /// # Errors
+    ///
+    /// See [`find_trashed`](Self::find_trashed); also fails if the change
+    /// cannot be saved.
+    pub fn restore_task(&mut self, key: &str) -> Result<&Task, TodoError> {
+        let id = self.find_trashed(key)?.id;
+        self.set_deleted(id, None, OperationKind::Restore)
     }
 
-    pub fn delete_task(&mut self, name: &str) {
-        if self.tasks.remove(name).is_some() {
-            if let Err(e) = self.save_to_file() {
-                eprintln!("Error saving changes: {e}");
+    /// Permanently removes the tasks that were moved to the trash before
+    /// `deleted_before` (all of them for `None`) and returns them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the change cannot be saved.
+    pub fn empty_trash(&mut self, deleted_before: Option<DateTime<Utc>>) -> Result<Vec<Task>, TodoError> {
+        let ids: Vec<u64> = self.trash()
+            .filter(|task| deleted_before.is_none_or(|cutoff| task.deleted.is_some_and(|deleted| deleted < cutoff)))
+            .map(|task| task.id)
+            .collect();
+        let mut removed = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(task) = self.list.tasks.remove(&id) {
+                self.store.delete(id, &self.list)?;
+                self.record(OperationKind::Purge, Some(task.clone()), id)?;
+                removed.push(task);
             }
-            println!("Task deleted successfully!");
-        } else {
-            println!("Task not found!");
         }
+        Ok(removed)
     }
 
-    pub fn select_tasks(&self, input: &str) {
-        let (all_tasks, predicate) = if input.trim() == "*" {
-            (true, "")
-        } else if input.starts_with("* where ") {
-            (false, input.trim_start_matches("* where ").trim())
-        } else {
-            (false, input.trim())
+    /// The recorded changes to a task, oldest first. `key` is resolved like
+    /// in [`find_task`](Self::find_task), then among the trashed tasks; a
+    /// numeric ID also finds tasks that were removed for good, as long as
+    /// the history mentions them.
+    ///
+    /// # Errors
+    ///
+    /// See [`find_task`](Self::find_task); also fails if the history cannot
+    /// be read.
+    pub fn history(&self, key: &str) -> Result<Vec<Change>, TodoError> {
+        let changes = |id: u64| -> Result<Vec<Change>, TodoError> {
+            Ok(self.history.changes()?.into_iter().filter(|change| change.task == id).collect())
         };
+        match self.find_task(key).or_else(|_| self.find_trashed(key)) {
+            Ok(task) => changes(task.id),
+            Err(e) => {
+                let Ok(id) = key.trim_start_matches('#').parse() else {
+                    return Err(e);
+                };
+                let removed = changes(id)?;
+                if removed.is_empty() {
+                    return Err(e);
+                }
+                Ok(removed)
+            }
+        }
+    }
 
-        let filtered_tasks: Vec<&Task> = if all_tasks {
-            self.tasks.values().collect()
-        } else {
-            self.tasks.values()
-                .filter(|task| Self::evaluate_predicate(task, predicate))
-                .collect()
-        };
+    /// The IDs of the tasks changed at or after `since`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the history cannot be read.
+    pub fn changed_since(&self, since: DateTime<Utc>) -> Result<BTreeSet<u64>, TodoError> {
+        Ok(self.history.changes()?.into_iter().filter(|change| change.at >= since).map(|change| change.task).collect())
+    }
 
-        if filtered_tasks.is_empty() {
-            println!("No tasks match the given criteria.");
-        } else {
-            for task in filtered_tasks {
-                println!("{task:#?}");
-            }
+    /// Whether the journal could be read, which [`undo`](Self::undo) and
+    /// [`redo`](Self::redo) need.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Conflict`] if the journal file is damaged and no
+    /// change has replaced it yet.
+    pub fn check_journal(&self) -> Result<(), TodoError> {
+        match (&self.journal_damage, &self.journal_path) {
+            (Some(reason), Some(path)) => Err(TodoError::Conflict(format!(
+                "the undo journal {} is damaged ({reason}); earlier changes cannot be undone or redone",
+                path.display()
+            ))),
+            _ => Ok(()),
         }
     }
 
-    fn evaluate_predicate(task: &Task, predicate: &str) -> bool {
-        if predicate == "*" {
-            return true;
+    /// The operations [`undo`](Self::undo) would revert for `steps`, newest
+    /// first.
+    pub fn undoable(&self, steps: usize) -> impl Iterator<Item = &Operation> {
+        self.journal.undo.iter().rev().take(steps)
+    }
+
+    /// The operations [`redo`](Self::redo) would reapply for `steps`, in the
+    /// order it would apply them.
+    pub fn redoable(&self, steps: usize) -> impl Iterator<Item = &Operation> {
+        self.journal.redo.iter().rev().take(steps)
+    }
+
+    /// Reverts the last `steps` changes (fewer if there are not that many)
+    /// and returns them, newest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Conflict`], changing nothing, if a task was
+    /// modified since without going through the journal or the journal is
+    /// damaged (see [`check_journal`](Self::check_journal)). Also fails if the
+    /// store, the journal or the history cannot be written; the changes
+    /// reverted before the failing one stay reverted and journalled.
+    pub fn undo(&mut self, steps: usize) -> Result<Vec<Operation>, TodoError> {
+        self.step(steps, true)
+    }
+
+    /// Reapplies the last `steps` undone changes and returns them.
+    ///
+    /// # Errors
+    ///
+    /// See [`undo`](Self::undo).
+    pub fn redo(&mut self, steps: usize) -> Result<Vec<Operation>, TodoError> {
+        self.step(steps, false)
+    }
+
+    /// Runs a `select` query (see the README for the syntax) over the tasks
+    /// that are not in the trash.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Query`] if the query does not parse.
+    pub fn select(&self, input: &str) -> Result<Vec<&Task>, TodoError> {
+        self.run_query(input, false)
+    }
+
+    /// Like [`select`](Self::select), including the tasks in the trash.
+    ///
+    /// # Errors
+    ///
+    /// See [`select`](Self::select).
+    pub fn select_with_trash(&self, input: &str) -> Result<Vec<&Task>, TodoError> {
+        self.run_query(input, true)
+    }
+
+    fn run_query(&self, input: &str, include_trash: bool) -> Result<Vec<&Task>, TodoError> {
+        let query = self.parse_query(input)?;
+        let candidates = match &query.filter {
+            Some(filter) => self.store.candidates(filter)?,
+            None => None,
+        };
+        let visible = |task: &&Task| include_trash || !task.is_trashed();
+        Ok(candidates.map_or_else(
+            || query.apply(self.list.tasks.values().filter(visible)),
+            |ids| query.apply(ids.iter().filter_map(|id| self.list.tasks.get(id)).filter(visible)),
+        ))
+    }
+
+    /// Runs a `select` query against the tasks that were not in the trash
+    /// at `at`, as they were then.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Query`] if the query does not parse, or
+    /// [`TodoError::Unsupported`] if the store does not keep a history (see
+    /// [`TaskStore::as_of`]).
+    pub fn select_as_of(&self, at: DateTime<Utc>, input: &str) -> Result<Vec<Task>, TodoError> {
+        self.run_query_as_of(at, input, false)
+    }
+
+    /// Like [`select_as_of`](Self::select_as_of), including the tasks that
+    /// were in the trash at `at`.
+    ///
+    /// # Errors
+    ///
+    /// See [`select_as_of`](Self::select_as_of).
+    pub fn select_as_of_with_trash(&self, at: DateTime<Utc>, input: &str) -> Result<Vec<Task>, TodoError> {
+        self.run_query_as_of(at, input, true)
+    }
+
+    fn run_query_as_of(&self, at: DateTime<Utc>, input: &str, include_trash: bool) -> Result<Vec<Task>, TodoError> {
+        let query = self.parse_query(input)?;
+        let list = self.store.as_of(at)?;
+        let visible = |task: &&Task| include_trash || !task.is_trashed();
+        Ok(query.apply(list.tasks.values().filter(visible)).into_iter().cloned().collect())
+    }
+
+    /// Replaces the tasks with `list`, e.g. one loaded from another store,
+    /// keeping their IDs. Returns the number of tasks imported.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Conflict`] if there already are tasks and
+    /// `replace` is not set, or [`TodoError::Storage`] if the list cannot be
+    /// saved.
+    pub fn import(&mut self, list: TaskList, replace: bool) -> Result<usize, TodoError> {
+        if !replace && !self.list.tasks.is_empty() {
+            return Err(TodoError::Conflict(format!(
+                "the task list already has {} task(s)",
+                self.list.tasks.len()
+            )));
         }
+        let before = std::mem::replace(&mut self.list, list);
+        self.save()?;
+        let at = date::now();
+        let ids: BTreeSet<u64> = before.tasks.keys().chain(self.list.tasks.keys()).copied().collect();
+        let changes = ids.into_iter()
+            .flat_map(|id| Change::between(before.tasks.get(&id), self.list.tasks.get(&id), "import", at, &self.user))
+            .collect();
+        self.history.append(changes)?;
+        self.journal = Journal::default();
+        self.save_journal()?;
+        Ok(self.list.tasks.len())
+    }
 
-        let conditions: Vec<&str> = predicate.split(" and ").collect();
-               
-        conditions.iter().all(|&condition| {
-            let parts: Vec<&str> = condition.split_whitespace().collect();
+    /// Writes the whole list to the store.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the store cannot be written.
+    pub fn save(&mut self) -> Result<(), TodoError> {
+        self.store.save(&self.list)
+    }
 
+    /// Stores the task that was just added or changed and hands it back.
+    fn commit(&mut self, id: u64) -> Result<&Task, TodoError> {
+        if let Some(task) = self.list.tasks.get(&id) {
+            self.store.upsert(task, &self.list)?;
+        }
+        self.lookup(id)
+    }
 
+    fn set_deleted(&mut self, id: u64, deleted: Option<DateTime<Utc>>, kind: OperationKind) -> Result<&Task, TodoError> {
+        let before = self.list.tasks.get(&id).cloned();
+        if let Some(task) = self.list.tasks.get_mut(&id) {
+            task.deleted = deleted;
+        }
+        self.commit(id)?;
+        self.record(kind, before, id)?;
+        self.lookup(id)
+    }
 
-            if parts.len() < 3 || parts.len() > 4  {
-                return false;
+    fn change(&mut self, key: &str, changes: TaskChanges, kind: OperationKind) -> Result<&Task, TodoError> {
+        let task = self.find_task(key)?;
+        let (id, from) = (task.id, task.status);
+        if let Some(to) = changes.status.filter(|to| !self.transitions.allows(from, *to)) {
+            return Err(TodoError::Transition { id, from, to, allowed: self.transitions.from(from).collect() });
+        }
+        if changes.is_empty() {
+            return self.lookup(id);
+        }
+        let before = self.list.tasks.get(&id).cloned();
+        if let Some(task) = self.list.tasks.get_mut(&id) {
+            changes.apply_to(task);
+            if from.is_closed() && !task.status.is_closed() {
+                task.reopened = Some(date::now());
             }
+        }
+        self.commit(id)?;
+        self.record(kind, before, id)?;
+        self.lookup(id)
+    }
 
-            let (field, operator, value) = (parts[0], parts[1], &parts[2..].join(" "));
-            match field {
-                "name" => Self::compare_string(&task.name, operator, value),
-                "date" => Self::compare_date(&task.date, operator, value),
-                "category" => Self::compare_string(&task.category, operator, value),
-                "description" => Self::compare_string(&task.description, operator, value),
-                _ => false,
-            }
-        })
+    /// Journals a change to task `id`, which was `before` and is now
+    /// whatever the list holds. Changes that left the task as it was are
+    /// not recorded.
+    fn record(&mut self, kind: OperationKind, before: Option<Task>, id: u64) -> Result<(), TodoError> {
+        let after = self.list.tasks.get(&id).cloned();
+        if before == after {
+            return Ok(());
+        }
+        let at = date::now();
+        self.history.append(Change::between(before.as_ref(), after.as_ref(), &kind.to_string(), at, &self.user))?;
+        self.journal.record(Operation {
+            kind,
+            at,
+            before,
+            after,
+        });
+        self.save_journal()
     }
 
-    fn compare_string(field: &str, operator: &str, value: &str) -> bool {
-        match operator {
-            "=" => field == value.trim_matches('"'),
-            "like" => field.contains(value.trim_matches('"')),
-            _ => false,
+    /// Parses a query, looking up `changed since` in the history.
+    fn parse_query(&self, input: &str) -> Result<query::ast::Query, TodoError> {
+        let mut query = query::parse(input)?;
+        if let Some(filter) = query.filter.as_mut().filter(|filter| filter.mentions_changes()) {
+            let changes = self.history.changes()?;
+            filter.resolve_changes(&|since| {
+                changes.iter().filter(|change| change.at >= since).map(|change| change.task).collect()
+            });
         }
+        Ok(query)
     }
 
-    fn compare_date(date: &DateTime<Utc>, operator: &str, value: &str) -> bool {
-        let compared_date = match NaiveDateTime::parse_from_str(value, "%d-%m-%Y %H:%M") {
-            Ok(date) => date,
-            Err(e) => {
-                eprintln!("Error parsing date and time: {e}");
-                return false; 
-            }
+    fn save_journal(&mut self) -> Result<(), TodoError> {
+        let Some(path) = &self.journal_path else {
+            return Ok(());
         };
+        if self.journal_damage.is_some() {
+            JsonFileStore::preserve_corrupt(path)?;
+        }
+        self.journal.save(path)?;
+        self.journal_damage = None;
+        Ok(())
+    }
+
+    /// Undoes (or redoes) up to `steps` operations, after checking that
+    /// every task is still in the state the operation left it in.
+    fn step(&mut self, steps: usize, undo: bool) -> Result<Vec<Operation>, TodoError> {
+        self.check_journal()?;
+        let stack = if undo { &self.journal.undo } else { &self.journal.redo };
+        let operations: Vec<Operation> = stack.iter().rev().take(steps).cloned().collect();
 
-        println!("operatorn: {operator}");
-        match operator {
-            "<" => date.naive_local() < compared_date,
-            "=" => date.naive_local() == compared_date,
-            ">=" => date.naive_local() >= compared_date,
-            ">" => date.naive_local() > compared_date,
-            _ => false,
+        let mut tasks = self.list.tasks.clone();
+        for operation in &operations {
+            let (expected, target) = if undo { (&operation.after, &operation.before) } else { (&operation.before, &operation.after) };
+            if tasks.get(&operation.id()) != expected.as_ref() {
+                return Err(TodoError::Conflict(format!(
+                    "task #{} was changed after the {} that would be {}",
+                    operation.id(),
+                    operation.kind,
+                    if undo { "undone" } else { "redone" }
+                )));
+            }
+            set(&mut tasks, operation.id(), target.clone());
         }
+
+        let at = date::now();
+        let mut applied = Vec::with_capacity(operations.len());
+        let mut failure = None;
+        for operation in operations {
+            if let Err(e) = self.apply_step(&operation, undo, at) {
+                failure = Some(e);
+                break;
+            }
+            applied.push(operation);
+        }
+        self.save_journal()?;
+        failure.map_or(Ok(applied), Err)
     }
 
-    fn get_file_path() -> PathBuf {
-        let env = env::var("APP_ENV").unwrap_or_else(|_| "production".to_string());
-        let file_name = match env.as_str() {
-            "test" => "test_db.json",
-            _ => "db.json",
+    /// Writes one checked step of [`step`](Self::step) to the store, then
+    /// moves it to the other journal stack and records it in the history. If
+    /// the store cannot be written, nothing changes.
+    fn apply_step(&mut self, operation: &Operation, undo: bool, at: DateTime<Utc>) -> Result<(), TodoError> {
+        let id = operation.id();
+        let target = if undo { &operation.before } else { &operation.after };
+        let action = format!("{} {}", if undo { "undo" } else { "redo" }, operation.kind);
+        let changes = Change::between(self.list.tasks.get(&id), target.as_ref(), &action, at, &self.user);
+
+        let next_id = self.list.next_id;
+        if target.is_some() {
+            self.list.next_id = next_id.max(id + 1);
+        }
+        let previous = set(&mut self.list.tasks, id, target.clone());
+        let stored = match target {
+            Some(task) => self.store.upsert(task, &self.list),
+            None => self.store.delete(id, &self.list),
+        };
+        if let Err(e) = stored {
+            set(&mut self.list.tasks, id, previous);
+            self.list.next_id = next_id;
+            return Err(e);
+        }
+
+        let (from, to) = if undo {
+            (&mut self.journal.undo, &mut self.journal.redo)
+        } else {
+            (&mut self.journal.redo, &mut self.journal.undo)
         };
-        env::var("TODO_FILE").map_or_else(|_| PathBuf::from(file_name), PathBuf::from)
+        to.extend(from.pop());
+        self.history.append(changes)
     }
 
+    fn lookup(&self, id: u64) -> Result<&Task, TodoError> {
+        self.list.tasks.get(&id).ok_or_else(|| TodoError::NotFound(id.to_string()))
+    }
 
-    fn save_to_file(&self) -> io::Result<()> {
-        let json = serde_json::to_string_pretty(&self)?;
-        let path = Self::get_file_path();
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(path)?;
-        file.write_all(json.as_bytes())?;
-        Ok(())
+}
+
+/// Puts `task` (or, for `None`, no task) under `id` and returns what was
+/// there before.
+fn set(tasks: &mut BTreeMap<u64, Task>, id: u64, task: Option<Task>) -> Option<Task> {
+    match task {
+        Some(task) => tasks.insert(id, task),
+        None => tasks.remove(&id),
     }
+}
 
-    pub fn load_from_file() -> Self {
-        let path = Self::get_file_path();
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(path);
-
-        match file {
-            Ok(mut file) => {
-                let mut contents = String::new();
-                match file.read_to_string(&mut contents) {
-                    Ok(_) => {
-                        if contents.trim().is_empty() {
-                            println!("File is empty. Creating a new TodoListService.");
-                            let new_service = Self::new();
-                            if let Err(e) = new_service.save_to_file() {
-                                eprintln!("Error saving new TodoListService: {e}. Proceeding with in-memory service.");
-                            }
-                            new_service
-                        } else {
-                            match serde_json::from_str(&contents) {
-                                Ok(service) => service,
-                                Err(e) => {
-                                    eprintln!("Error parsing JSON: {e}. Creating a new TodoListService.");
-                                    let new_service = Self::new();
-                                    if let Err(e) = new_service.save_to_file() {
-                                        eprintln!("Error saving new TodoListService: {e}. Proceeding with in-memory service.");
-                                    }
-                                    new_service
-                                }
-                            }
-                        }
-                    },
-                    Err(e) => {
-                        eprintln!("Error reading file: {e}. Creating a new TodoListService.");
-                        let new_service = Self::new();
-                        if let Err(e) = new_service.save_to_file() {
-                            eprintln!("Error saving new TodoListService: {e}. Proceeding with in-memory service.");
-                        }
-                        new_service
-                    }
-                }
-            },
-            Err(e) => {
-                eprintln!("Error opening file: {e}. Creating a new TodoListService.");
-                let new_service = Self::new();
-                if let Err(e) = new_service.save_to_file() {
-                    eprintln!("Error saving new TodoListService: {e}. Proceeding with in-memory service.");
-                }
-                new_service
-            }
-        }
+impl Default for TodoListService {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -287,88 +699,536 @@ impl TodoListService {
 mod tests {
     use super::*;
     use chrono::Utc;
-    use std::env;
+    use std::cell::Cell;
+    use std::fs;
+    use std::rc::Rc;
+    use crate::storage::SqliteStore;
+
+    fn task_named<'a>(todo_list: &'a TodoListService, name: &str) -> Option<&'a Task> {
+        todo_list.tasks().find(|task| task.name == name)
+    }
+
+    /// A memory store that refuses to write the task `fail_on` names.
+    #[derive(Debug, Default)]
+    struct FailingStore {
+        inner: MemoryStore,
+        fail_on: Rc<Cell<Option<u64>>>,
+    }
 
-    fn setup() {
-        env::set_var("APP_ENV", "test");
+    impl FailingStore {
+        fn check(&self, id: u64) -> Result<(), TodoError> {
+            if self.fail_on.get() == Some(id) {
+                return Err(TodoError::Storage(std::io::Error::other(format!("cannot write task {id}"))));
+            }
+            Ok(())
+        }
+    }
+
+    impl TaskStore for FailingStore {
+        fn load(&mut self) -> Result<TaskList, TodoError> {
+            self.inner.load()
+        }
+
+        fn save(&mut self, list: &TaskList) -> Result<(), TodoError> {
+            self.inner.save(list)
+        }
+
+        fn upsert(&mut self, task: &Task, list: &TaskList) -> Result<(), TodoError> {
+            self.check(task.id)?;
+            self.inner.upsert(task, list)
+        }
+
+        fn delete(&mut self, id: u64, list: &TaskList) -> Result<(), TodoError> {
+            self.check(id)?;
+            self.inner.delete(id, list)
+        }
     }
 
     #[test]
     fn test_add_task() {
-        setup();
         let mut todo_list = TodoListService::new();
         let name = "Test Task".to_string();
         let description = "Test Description".to_string();
         let date = Utc::now();
         let category = "Test Category".to_string();
 
-        todo_list.add_task(name.clone(), description.clone(), date, category.clone());
+        todo_list.add_task(name.clone(), description.clone(), date, category.clone(), false).expect("Task should be added");
 
-        assert!(todo_list.tasks.contains_key(&name));
+        assert!(task_named(&todo_list, &name).is_some());
 
-        let task = todo_list.tasks.get(&name).expect("Task not found in the todo list");
+        let task = task_named(&todo_list, &name).expect("Task not found in the todo list");
         assert_eq!(task.description, description);
         assert_eq!(task.category, category);
-        assert!(!task.status);
+        assert_eq!(task.status, Status::Pending);
     }
 
     #[test]
     fn test_update_task() {
-        setup();
         let mut todo_list = TodoListService::new();
         let name = "Test Task".to_string();
         let description = "Test Description".to_string();
         let date = Utc::now();
         let category = "Test Category".to_string();
 
-        todo_list.add_task(name.clone(), description.clone(), date, category.clone());
+        todo_list.add_task(name.clone(), description.clone(), date, category.clone(), false).expect("Task should be added");
 
-        assert!(todo_list.tasks.contains_key(&name));
+        assert!(task_named(&todo_list, &name).is_some());
 
-        let task = todo_list.tasks.get(&name).expect("Task not found in the todo list");
+        let task = task_named(&todo_list, &name).expect("Task not found in the todo list");
         assert_eq!(task.description, description);
         assert_eq!(task.category, category);
-        assert!(!task.status);
+        assert_eq!(task.status, Status::Pending);
     }
 
 
     #[test]
     fn test_mark_done() {
-        setup();
         let mut todo_list = TodoListService::new();
         let name = "Test Task".to_string();
-        todo_list.add_task(name.clone(), "Description".to_string(), Utc::now(), "Category".to_string());
+        todo_list.add_task(name.clone(), "Description".to_string(), Utc::now(), "Category".to_string(), false).expect("Task should be added");
 
-        todo_list.mark_done(&name);
+        todo_list.mark_done(&name).expect("Task should be marked done");
 
-        let task = todo_list.tasks.get(&name).expect("Task not found in the todo list");
-        assert!(task.status);
+        let task = task_named(&todo_list, &name).expect("Task not found in the todo list");
+        assert!(task.is_done());
     }
 
     #[test]
     fn test_delete_task() {
-        setup();
         let mut todo_list = TodoListService::new();
         let name = "Test Task".to_string();
-        todo_list.add_task(name.clone(), "Description".to_string(), Utc::now(), "Category".to_string());
+        todo_list.add_task(name.clone(), "Description".to_string(), Utc::now(), "Category".to_string(), false).expect("Task should be added");
 
-        todo_list.delete_task(&name);
+        todo_list.delete_task(&name).expect("Task should be deleted");
 
-        assert!(!todo_list.tasks.contains_key(&name));
+        assert!(task_named(&todo_list, &name).is_none());
     }
 
     #[test]
     fn test_select_tasks() {
-        setup();
         let mut todo_list = TodoListService::new();
-        todo_list.add_task("Task 1".to_string(), "Unit test 1".to_string(), Utc::now(), "Category1".to_string());
-        todo_list.add_task("Task 2".to_string(), "Unit test 2".to_string(), Utc::now(), "Category2".to_string());
+        todo_list.add_task("Task 1".to_string(), "Unit test 1".to_string(), Utc::now(), "Category1".to_string(), false).expect("Task should be added");
+        todo_list.add_task("Task 2".to_string(), "Unit test 2".to_string(), Utc::now(), "Category2".to_string(), false).expect("Task should be added");
 
-        let filtered_tasks: Vec<&Task> = todo_list.tasks.values()
-            .filter(|task| TodoListService::evaluate_predicate(task, "category = \"Category1\""))
-            .collect();
+        let filtered_tasks = todo_list.select("category = \"Category1\"")
+            .expect("Query should parse");
+
+        assert_eq!(filtered_tasks.len(), 1);
+        assert_eq!(filtered_tasks[0].name, "Task 1");
+    }
+
+    #[test]
+    fn test_select_tasks_with_or_and_not() {
+        let mut todo_list = TodoListService::new();
+        todo_list.add_task("Task 1".to_string(), "final report".to_string(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+        todo_list.add_task("Task 2".to_string(), "draft plan".to_string(), Utc::now(), "ops".to_string(), false).expect("Task should be added");
+        todo_list.add_task("Task 3".to_string(), "groceries".to_string(), Utc::now(), "home".to_string(), false).expect("Task should be added");
+
+        let filtered_tasks = todo_list
+            .select("(category = work or category = ops) and not description like draft")
+            .expect("Query should parse");
 
         assert_eq!(filtered_tasks.len(), 1);
         assert_eq!(filtered_tasks[0].name, "Task 1");
     }
+
+    #[test]
+    fn test_select_tasks_by_status() {
+        let mut todo_list = TodoListService::new();
+        todo_list.add_task("Task 1".to_string(), "Open".to_string(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+        todo_list.add_task("Task 2".to_string(), "Closed".to_string(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+        todo_list.mark_done("Task 2").expect("Task should be marked done");
+
+        let pending = todo_list.select("* where status = pending").expect("Query should parse");
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].name, "Task 1");
+
+        let done = todo_list.select("* where category = work and status=on").expect("Query should parse");
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].name, "Task 2");
+    }
+
+    #[test]
+    fn test_select_tasks_with_extended_operators() {
+        let mut todo_list = TodoListService::new();
+        let early = date::parse("01-01-2023 10:00").expect("valid date");
+        let late = date::parse("01-06-2023 10:00").expect("valid date");
+        todo_list.add_task("OPS-1 deploy".to_string(), "draft".to_string(), early, "ops".to_string(), false).expect("Task should be added");
+        todo_list.add_task("DEV-2 review".to_string(), "final".to_string(), late, "dev".to_string(), false).expect("Task should be added");
+        todo_list.add_task("OPS-3 rollback".to_string(), "final".to_string(), late, "home".to_string(), false).expect("Task should be added");
+
+        let names = |query: &str| {
+            let mut names: Vec<String> = todo_list
+                .select(query)
+                .expect("Query should parse")
+                .into_iter()
+                .map(|task| task.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(names("date <= 01-01-2023 10:00"), vec!["OPS-1 deploy"]);
+        assert_eq!(names("date != 01-01-2023 10:00"), vec!["DEV-2 review", "OPS-3 rollback"]);
+        assert_eq!(names("name starts with OPS and description not like draft"), vec!["OPS-3 rollback"]);
+        assert_eq!(names("name ends with review"), vec!["DEV-2 review"]);
+        assert_eq!(names("category in (ops, dev)"), vec!["DEV-2 review", "OPS-1 deploy"]);
+        assert_eq!(names("category < home"), vec!["DEV-2 review"]);
+        assert_eq!(names("status <> done"), vec!["DEV-2 review", "OPS-1 deploy", "OPS-3 rollback"]);
+    }
+
+    #[test]
+    fn test_select_tasks_with_regex() {
+        let mut todo_list = TodoListService::new();
+        todo_list.add_task("OPS-1234 rotate keys".to_string(), "First line\nurgent".to_string(), Utc::now(), "ops".to_string(), false).expect("Task should be added");
+        todo_list.add_task("ops backlog".to_string(), "later".to_string(), Utc::now(), "ops".to_string(), false).expect("Task should be added");
+
+        let strict = todo_list.select(r"name matches /^OPS-\d{4}\b/").expect("Query should parse");
+        assert_eq!(strict.len(), 1);
+        assert_eq!(strict[0].name, "OPS-1234 rotate keys");
+
+        let insensitive = todo_list.select("name matches /^ops/i").expect("Query should parse");
+        assert_eq!(insensitive.len(), 2);
+
+        let multiline = todo_list.select("description matches /^urgent$/m").expect("Query should parse");
+        assert_eq!(multiline.len(), 1);
+    }
+
+    #[test]
+    fn test_select_tasks_ordered_and_paginated() {
+        let mut todo_list = TodoListService::new();
+        for (name, date) in [("b", "03-01-2023 10:00"), ("a", "01-01-2023 10:00"), ("c", "02-01-2023 10:00"), ("d", "02-01-2023 10:00")] {
+            let date = date::parse(date).expect("valid date");
+            todo_list.add_task(name.to_string(), String::new(), date, "work".to_string(), false).expect("Task should be added");
+        }
+
+        let names = |query: &str| -> Vec<String> {
+            todo_list
+                .select(query)
+                .expect("Query should parse")
+                .into_iter()
+                .map(|task| task.name.clone())
+                .collect()
+        };
+
+        assert_eq!(names("*"), vec!["a", "c", "d", "b"]);
+        assert_eq!(names("* order by date desc"), vec!["b", "c", "d", "a"]);
+        assert_eq!(names("* order by name desc limit 2"), vec!["d", "c"]);
+        assert_eq!(names("* where category = work order by name limit 2 offset 1"), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn test_add_task_assigns_stable_ids_and_refuses_duplicates() {
+        let mut todo_list = TodoListService::new();
+        todo_list.add_task("Same".to_string(), "First".to_string(), Utc::now(), "A".to_string(), false).expect("Task should be added");
+        todo_list.add_task("Other".to_string(), "Second".to_string(), Utc::now(), "A".to_string(), false).expect("Task should be added");
+        let duplicate = todo_list.add_task("Same".to_string(), "Duplicate".to_string(), Utc::now(), "A".to_string(), false);
+        assert!(matches!(duplicate, Err(TodoError::Duplicate { id: 1, .. })));
+        assert_eq!(todo_list.list.tasks.len(), 2);
+
+        todo_list.add_task("Same".to_string(), "Forced".to_string(), Utc::now(), "A".to_string(), true).expect("Task should be added");
+        assert_eq!(todo_list.list.tasks.keys().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        // The name is now ambiguous, so only IDs resolve it.
+        let id = |key: &str| todo_list.find_task(key).map(|task| task.id);
+        assert!(matches!(id("Same"), Err(TodoError::Ambiguous { ids, .. }) if ids == vec![1, 3]));
+        assert_eq!(id("3").ok(), Some(3));
+        assert_eq!(id("#1").ok(), Some(1));
+        assert_eq!(id("Other").ok(), Some(2));
+        assert!(matches!(id("Missing"), Err(TodoError::NotFound(_))));
+
+        todo_list.delete_task("2").expect("Task should be deleted");
+        todo_list.add_task("New".to_string(), "Fresh".to_string(), Utc::now(), "A".to_string(), false).expect("Task should be added");
+        assert!(task_named(&todo_list, "New").is_some_and(|task| task.id == 4));
+    }
+
+    #[test]
+    fn test_select_tasks_by_id() {
+        let mut todo_list = TodoListService::new();
+        for name in ["a", "b", "c"] {
+            todo_list.add_task(name.to_string(), String::new(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+        }
+
+        let names = |query: &str| -> Vec<String> {
+            todo_list
+                .select(query)
+                .expect("Query should parse")
+                .into_iter()
+                .map(|task| task.name.clone())
+                .collect()
+        };
+
+        assert_eq!(names("id in (1, 3) order by id desc"), vec!["c", "a"]);
+        assert_eq!(names("id >= 2 order by id"), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn test_update_task_changes_only_given_fields() {
+        let mut todo_list = TodoListService::new();
+        let date = date::parse("01-01-2023 10:00").expect("valid date");
+        todo_list.add_task("Task".to_string(), "Description".to_string(), date, "Category".to_string(), false).expect("Task should be added");
+
+        let new_date = date::parse("02-01-2023 10:00").expect("valid date");
+        todo_list.update_task("Task", TaskChanges {
+            name: Some("Renamed".to_string()),
+            date: Some(new_date),
+            status: Some(Status::Done),
+            ..TaskChanges::default()
+        }).expect("Task should be updated");
+
+        let task = task_named(&todo_list, "Renamed").expect("Task not found in the todo list");
+        assert_eq!(task.id, 1);
+        assert_eq!(task.description, "Description");
+        assert_eq!(task.category, "Category");
+        assert_eq!(task.date, new_date);
+        assert!(task.is_done());
+    }
+
+    #[test]
+    fn test_open_saves_changes_back_to_the_file() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("tasks.json");
+
+        let mut todo_list = TodoListService::open(&path).expect("missing file opens empty");
+        assert_eq!(todo_list.tasks().count(), 0);
+        todo_list.add_task("Task".to_string(), String::new(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+        drop(todo_list);
+
+        let reopened = TodoListService::open(&path).expect("saved file should load");
+        assert_eq!(reopened.find_task("1").map(|task| task.name.as_str()).ok(), Some("Task"));
+        drop(reopened);
+
+        fs::write(&path, "not json").expect("write file");
+        assert!(matches!(TodoListService::open(&path), Err(TodoError::Corrupt(_))));
+    }
+
+    #[test]
+    fn test_open_waits_for_the_lock() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("tasks.json");
+
+        let held = TodoListService::open(&path).expect("first open");
+        let blocked = TodoListService::open_with_timeout(&path, Duration::from_millis(100));
+        assert!(matches!(blocked, Err(TodoError::Locked { .. })));
+
+        drop(held);
+        assert!(TodoListService::open_with_timeout(&path, Duration::ZERO).is_ok());
+    }
+
+    #[test]
+    fn test_changes_reach_the_store() {
+        let mut todo_list = TodoListService::with_store(Box::<MemoryStore>::default()).expect("memory store loads");
+        todo_list.add_task("Kept".to_string(), String::new(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+        todo_list.add_task("Dropped".to_string(), String::new(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+        todo_list.mark_done("Kept").expect("Task should be marked done");
+        todo_list.delete_task("Dropped").expect("Task should be deleted");
+
+        let stored = todo_list.store.load().expect("memory store loads");
+        let stored: Vec<_> = stored.tasks.values().map(|task| (task.name.as_str(), task.status, task.is_trashed())).collect();
+        assert_eq!(stored, vec![("Kept", Status::Done, false), ("Dropped", Status::Pending, true)]);
+        assert_eq!(todo_list.store.load().expect("memory store loads").next_id, 3);
+    }
+
+    #[test]
+    fn test_undo_and_redo() {
+        let mut todo_list = TodoListService::new();
+        todo_list.add_task("Report".to_string(), String::new(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+        todo_list.mark_done("Report").expect("Task should be marked done");
+        todo_list.delete_task("Report").expect("Task should be deleted");
+
+        let preview: Vec<String> = todo_list.undoable(2).map(|operation| operation.describe(true)).collect();
+        assert_eq!(preview, ["restore #1 \"Report\" from the trash", "change #1 \"Report\": status \"done\" -> \"pending\""]);
+
+        let undone = todo_list.undo(2).expect("Changes should be undone");
+        assert_eq!(undone.iter().map(|operation| operation.kind).collect::<Vec<_>>(), [OperationKind::Delete, OperationKind::Done]);
+        assert!(!todo_list.get(1).expect("Task should be restored").is_done());
+
+        todo_list.redo(1).expect("Change should be redone");
+        assert!(todo_list.get(1).expect("Task should exist").is_done());
+        assert_eq!(todo_list.redoable(5).count(), 1);
+
+        todo_list.update_task("Report", TaskChanges { name: Some("Renamed".to_string()), ..TaskChanges::default() }).expect("Task should be updated");
+        assert_eq!(todo_list.redoable(5).count(), 0);
+        assert_eq!(todo_list.undo(10).expect("Changes should be undone").len(), 3);
+        assert!(todo_list.tasks().next().is_none());
+        assert!(todo_list.undo(1).expect("Nothing left to undo").is_empty());
+    }
+
+    #[test]
+    fn test_undo_refuses_to_overwrite_later_changes() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("tasks.json");
+        let mut todo_list = TodoListService::open(&path).expect("open file");
+        todo_list.add_task("Report".to_string(), String::new(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+        todo_list.mark_done("Report").expect("Task should be marked done");
+        drop(todo_list);
+
+        let mut todo_list = TodoListService::open(&path).expect("reopen file");
+        assert_eq!(todo_list.undoable(5).count(), 2);
+        todo_list.list.tasks.get_mut(&1).expect("Task should exist").status = Status::Pending;
+        assert!(matches!(todo_list.undo(1), Err(TodoError::Conflict(_))));
+        assert_eq!(todo_list.undoable(5).count(), 2);
+    }
+
+    #[test]
+    fn test_undo_stops_at_the_first_failed_step() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let journal = dir.path().join("tasks.journal");
+        let store = FailingStore::default();
+        let fail_on = Rc::clone(&store.fail_on);
+        let mut todo_list = TodoListService::with_store(Box::new(store))
+            .expect("store loads")
+            .with_journal(&journal)
+            .expect("journal loads")
+            .with_history(dir.path().join("tasks.history"));
+        for name in ["First", "Second", "Third"] {
+            todo_list.add_task(name.to_string(), String::new(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+        }
+
+        fail_on.set(Some(1));
+        assert!(matches!(todo_list.undo(3), Err(TodoError::Storage(_))));
+        assert_eq!(todo_list.tasks().map(|task| task.id).collect::<Vec<_>>(), [1]);
+        assert_eq!(todo_list.store.load().expect("store loads").tasks.keys().copied().collect::<Vec<_>>(), [1]);
+        assert_eq!((todo_list.undoable(5).count(), todo_list.redoable(5).count()), (1, 2));
+        let saved = Journal::load(&journal).expect("journal loads");
+        assert_eq!((saved.undo.len(), saved.redo.len()), (1, 2));
+        let undone: Vec<u64> = todo_list.history.changes()
+            .expect("history loads")
+            .into_iter()
+            .filter(|change| change.action == "undo add")
+            .map(|change| change.task)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        assert_eq!(undone, [2, 3]);
+
+        fail_on.set(None);
+        assert_eq!(todo_list.undo(3).expect("Change should be undone").len(), 1);
+        assert!(todo_list.tasks().next().is_none());
+    }
+
+    #[test]
+    fn test_undo_sub_second_dates_on_sqlite() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("tasks.sqlite");
+        let journal = dir.path().join("tasks.journal");
+        let open = || {
+            let store = SqliteStore::open(&path, Duration::ZERO).expect("open store");
+            TodoListService::with_store(Box::new(store))
+                .expect("store loads")
+                .with_journal(&journal)
+                .expect("journal loads")
+        };
+        let date = date::parse("01-03-2024 09:00").expect("valid date") + chrono::Duration::nanoseconds(123_456_789);
+
+        let mut todo_list = open();
+        todo_list.add_task("Report".to_string(), String::new(), date, "work".to_string(), false).expect("Task should be added");
+        let later = TaskChanges { date: Some(date + chrono::Duration::milliseconds(1500)), ..TaskChanges::default() };
+        todo_list.update_task("Report", later).expect("Task should be updated");
+        drop(todo_list);
+
+        let mut todo_list = open();
+        assert_eq!(todo_list.undo(2).expect("Changes should be undone").len(), 2);
+        assert!(todo_list.tasks().next().is_none());
+        drop(todo_list);
+
+        let mut todo_list = open();
+        assert_eq!(todo_list.redo(2).expect("Changes should be redone").len(), 2);
+        assert_eq!(todo_list.get(1).map(|task| task.date), date::parse("01-03-2024 09:00").ok().map(|date| date + chrono::Duration::seconds(1)));
+    }
+
+    #[test]
+    fn test_deleted_tasks_go_to_the_trash() {
+        let mut todo_list = TodoListService::new();
+        for name in ["Kept", "Trashed", "Purged"] {
+            todo_list.add_task(name.to_string(), String::new(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+        }
+        todo_list.delete_task("Trashed").expect("Task should be trashed");
+        todo_list.delete_task("Purged").expect("Task should be trashed");
+
+        assert_eq!(todo_list.tasks().map(|task| task.name.as_str()).collect::<Vec<_>>(), ["Kept"]);
+        assert_eq!(todo_list.trash().count(), 2);
+        assert_eq!(todo_list.select("*").expect("Query should parse").len(), 1);
+        assert_eq!(todo_list.select_with_trash("*").expect("Query should parse").len(), 3);
+        assert!(matches!(todo_list.find_task("Trashed"), Err(TodoError::NotFound(_))));
+        todo_list.add_task("Trashed".to_string(), String::new(), Utc::now(), "work".to_string(), false).expect("Trashed names are free");
+
+        assert!(todo_list.empty_trash(Some(Utc::now() - chrono::Duration::days(30))).expect("Trash should be emptied").is_empty());
+        todo_list.restore_task("2").expect("Task should be restored");
+        let purged = todo_list.empty_trash(None).expect("Trash should be emptied");
+        assert_eq!(purged.iter().map(|task| task.name.as_str()).collect::<Vec<_>>(), ["Purged"]);
+        assert!(!todo_list.list.tasks.contains_key(&3));
+
+        todo_list.undo(1).expect("Purge should be undone");
+        assert!(todo_list.find_trashed("Purged").is_ok());
+    }
+
+    #[test]
+    fn test_history_records_who_changed_what() {
+        let mut todo_list = TodoListService::new().with_user("alice");
+        let date = date::parse("01-03-2024 09:00").expect("valid date");
+        todo_list.add_task("Report".to_string(), String::new(), date, "work".to_string(), false).expect("Task should be added");
+        todo_list.add_task("Other".to_string(), String::new(), date, "work".to_string(), false).expect("Task should be added");
+        let mut todo_list = todo_list.with_user("bob");
+        let moved = date::parse("08-03-2024 09:00").expect("valid date");
+        todo_list.update_task("Report", TaskChanges { date: Some(moved), ..TaskChanges::default() }).expect("Task should be updated");
+        todo_list.delete_task("Report").expect("Task should be trashed");
+
+        let history = todo_list.history("Report").expect("History should be readable");
+        assert!(history[..5].iter().all(|change| change.action == "add" && change.user == "alice"));
+        let later: Vec<String> = history[5..].iter().map(|change| format!("{} {} {}", change.user, change.action, change.describe())).collect();
+        assert_eq!(later[0], "bob update date \"2024-03-01T09:00:00Z\" -> \"2024-03-08T09:00:00Z\"");
+        assert!(later[1].starts_with("bob delete deleted none -> "));
+
+        let query = |since: DateTime<Utc>| format!("changed since '{}'", crate::utils::datetime_format::format(since));
+        let names = |tasks: Vec<&Task>| tasks.into_iter().map(|task| task.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(todo_list.select(&query(Utc::now() - chrono::Duration::hours(1))).expect("Query should parse")), ["Other"]);
+        assert!(todo_list.select(&query(Utc::now() + chrono::Duration::hours(1))).expect("Query should parse").is_empty());
+        assert_eq!(todo_list.changed_since(Utc::now() - chrono::Duration::hours(1)).expect("History should be readable"), [1, 2].into());
+    }
+
+    #[test]
+    fn test_status_changes_follow_the_transitions() {
+        let mut todo_list = TodoListService::new();
+        todo_list.add_task("Report".to_string(), String::new(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+
+        todo_list.set_status("Report", Status::InProgress).expect("Task should be started");
+        todo_list.set_status("Report", Status::Blocked).expect("Task should be blocked");
+        todo_list.mark_done("Report").expect("Task should be marked done");
+        let refused = todo_list.set_status("Report", Status::InProgress);
+        assert!(matches!(refused, Err(TodoError::Transition { from: Status::Done, to: Status::InProgress, ref allowed, .. }) if *allowed == [Status::Pending]));
+        assert!(todo_list.get(1).expect("Task should exist").is_done());
+        todo_list.set_status("Report", Status::Pending).expect("Task should be reopened");
+        let kinds: Vec<OperationKind> = todo_list.undoable(3).map(|operation| operation.kind).collect();
+        assert_eq!(kinds, [OperationKind::Reopen, OperationKind::Done, OperationKind::Block]);
+
+        let mut todo_list = todo_list.with_transitions("pending: in_progress; in_progress: done".parse().expect("rules parse"));
+        let skipped = TaskChanges { status: Some(Status::Done), ..TaskChanges::default() };
+        assert!(matches!(todo_list.update_task("Report", skipped.clone()), Err(TodoError::Transition { .. })));
+        todo_list.set_status("Report", Status::InProgress).expect("Task should be started");
+        todo_list.update_task("Report", skipped).expect("Task should be updated");
+        assert!(matches!(todo_list.set_status("Report", Status::Pending), Err(TodoError::Transition { ref allowed, .. }) if allowed.is_empty()));
+    }
+
+    #[test]
+    fn test_reopen_task() {
+        let mut todo_list = TodoListService::new();
+        todo_list.add_task("Report".to_string(), String::new(), Utc::now(), "work".to_string(), false).expect("Task should be added");
+        assert!(matches!(todo_list.reopen_task("Report"), Err(TodoError::Conflict(_))));
+
+        todo_list.mark_done("Report").expect("Task should be marked done");
+        let before = date::now();
+        let task = todo_list.reopen_task("Report").expect("Task should be reopened");
+        assert_eq!(task.status, Status::Pending);
+        assert!(task.reopened.is_some_and(|reopened| reopened >= before));
+        assert!(matches!(todo_list.reopen_task("Report"), Err(TodoError::Conflict(_))));
+
+        let history = todo_list.history("Report").expect("History should be readable");
+        let reopen: Vec<&str> = history.iter().filter(|change| change.action == "reopen").map(|change| change.field.as_str()).collect();
+        assert_eq!(reopen, ["status", "reopened"]);
+
+        todo_list.undo(1).expect("Reopen should be undone");
+        let task = todo_list.get(1).expect("Task should exist");
+        assert!(task.is_done() && task.reopened.is_none());
+    }
 }
@@ -0,0 +1,350 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::error::TodoError;
+use crate::models::status::Status;
+use crate::models::task::Task;
+use crate::utils::file_lock::FileLock;
+use crate::utils::{atomic_file, datetime_format, date};
+use super::{TaskList, TaskStore};
+
+/// How many events are appended between two snapshots.
+pub const SNAPSHOT_INTERVAL: usize = 100;
+
+/// A change to the task list, as recorded in the log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Event {
+    Add { task: Task },
+    Done { id: u64 },
+    Update { task: Task },
+    Delete { id: u64 },
+}
+
+/// One line of the log: an [`Event`] and when it happened.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    #[serde(with = "datetime_format")]
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// The state after the first `offset` bytes of the log.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    offset: u64,
+    list: TaskList,
+}
+
+/// Appends every change to a JSON Lines file instead of rewriting the list,
+/// so the full history stays available (see [`as_of`](TaskStore::as_of)).
+///
+/// The current list is rebuilt by replaying the log. To keep that cheap, the
+/// replayed state is written to `<path>.snapshot` every
+/// [`SNAPSHOT_INTERVAL`] events and later loads only replay what follows it;
+/// the log itself is never shortened.
+#[derive(Debug)]
+pub struct EventLogStore {
+    path: PathBuf,
+    /// The list as of the end of the log.
+    list: TaskList,
+    /// Events appended since the last snapshot.
+    pending: usize,
+    _lock: FileLock,
+}
+
+impl EventLogStore {
+    /// Opens the log at `path`, creating it on the first change. Like the
+    /// other file stores it locks `<path>.lock` until dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Locked`] if another process holds the log after
+    /// `timeout`.
+    pub fn open(path: impl Into<PathBuf>, timeout: Duration) -> Result<Self, TodoError> {
+        let path = path.into();
+        let lock = super::lock(&path, timeout)?;
+        Ok(Self {
+            path,
+            list: TaskList::default(),
+            pending: 0,
+            _lock: lock,
+        })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every recorded event, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the log cannot be read, or
+    /// [`TodoError::Corrupt`] if a line is not a valid event.
+    pub fn records(&self) -> Result<Vec<Record>, TodoError> {
+        parse(&read(&self.path)?)
+    }
+
+    fn snapshot_path(&self) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".snapshot");
+        self.path.with_file_name(name)
+    }
+
+    /// The snapshot, if there is one that fits a log of `len` bytes. A
+    /// missing or unreadable snapshot only means more has to be replayed.
+    fn snapshot(&self, len: usize) -> Option<Snapshot> {
+        let contents = fs::read_to_string(self.snapshot_path()).ok()?;
+        let snapshot: Snapshot = serde_json::from_str(&contents).ok()?;
+        usize::try_from(snapshot.offset).is_ok_and(|offset| offset <= len).then_some(snapshot)
+    }
+
+    fn write_snapshot(&mut self) -> Result<(), TodoError> {
+        let snapshot = Snapshot {
+            offset: fs::metadata(&self.path)?.len(),
+            list: self.list.clone(),
+        };
+        atomic_file::write(&self.snapshot_path(), serde_json::to_string(&snapshot)?.as_bytes())?;
+        self.pending = 0;
+        Ok(())
+    }
+
+    /// Appends `events`, applies them and takes a snapshot when one is due.
+    fn append(&mut self, events: Vec<Event>) -> Result<(), TodoError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let at = date::now();
+        let mut lines = String::new();
+        for event in events {
+            let record = Record { at, event };
+            lines.push_str(&serde_json::to_string(&record)?);
+            lines.push('\n');
+            apply(&mut self.list, record.event);
+            self.pending += 1;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(lines.as_bytes())?;
+        file.sync_data()?;
+
+        if self.pending >= SNAPSHOT_INTERVAL {
+            self.write_snapshot()?;
+        }
+        Ok(())
+    }
+
+    /// The event that turns the stored version of `task` into `task`, if it
+    /// changed at all.
+    fn change(&self, task: &Task) -> Option<Event> {
+        match self.list.tasks.get(&task.id) {
+            None => Some(Event::Add { task: task.clone() }),
+            Some(old) if old == task => None,
+            Some(old) if !old.is_done() && (Task { status: Status::Done, ..old.clone() }) == *task => Some(Event::Done { id: task.id }),
+            Some(_) => Some(Event::Update { task: task.clone() }),
+        }
+    }
+}
+
+impl TaskStore for EventLogStore {
+    /// Replays the log on top of the latest snapshot. A last line cut short
+    /// by a crash is dropped from the file; any other unreadable line is an
+    /// error.
+    fn load(&mut self) -> Result<TaskList, TodoError> {
+        let mut contents = read(&self.path)?;
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            contents.truncate(contents.rfind('\n').map_or(0, |i| i + 1));
+            File::options().write(true).open(&self.path)?.set_len(contents.len() as u64)?;
+        }
+
+        let (mut list, tail) = match self.snapshot(contents.len()) {
+            Some(snapshot) => {
+                let offset = usize::try_from(snapshot.offset).unwrap_or_default();
+                (snapshot.list, contents.get(offset..).unwrap_or_default())
+            }
+            None => (TaskList::default(), contents.as_str()),
+        };
+        let records = parse(tail)?;
+        self.pending = records.len();
+        for record in records {
+            apply(&mut list, record.event);
+        }
+        self.list = list.clone();
+        Ok(list)
+    }
+
+    /// Records the difference between the logged state and `list`.
+    fn save(&mut self, list: &TaskList) -> Result<(), TodoError> {
+        let mut events: Vec<Event> = self.list.tasks.keys()
+            .filter(|id| !list.tasks.contains_key(id))
+            .map(|&id| Event::Delete { id })
+            .collect();
+        events.extend(list.tasks.values().filter_map(|task| self.change(task)));
+        self.append(events)?;
+        self.list.next_id = self.list.next_id.max(list.next_id);
+        Ok(())
+    }
+
+    fn upsert(&mut self, task: &Task, list: &TaskList) -> Result<(), TodoError> {
+        let events = self.change(task).into_iter().collect();
+        self.append(events)?;
+        self.list.next_id = self.list.next_id.max(list.next_id);
+        Ok(())
+    }
+
+    fn delete(&mut self, id: u64, _list: &TaskList) -> Result<(), TodoError> {
+        self.append(vec![Event::Delete { id }])
+    }
+
+    /// Replays every event recorded up to `at`.
+    fn as_of(&self, at: DateTime<Utc>) -> Result<TaskList, TodoError> {
+        let mut list = TaskList::default();
+        for record in self.records()? {
+            if record.at <= at {
+                apply(&mut list, record.event);
+            }
+        }
+        Ok(list)
+    }
+}
+
+fn read(path: &Path) -> Result<String, TodoError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn parse(lines: &str) -> Result<Vec<Record>, TodoError> {
+    lines.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(TodoError::from))
+        .collect()
+}
+
+fn apply(list: &mut TaskList, event: Event) {
+    match event {
+        Event::Add { task } | Event::Update { task } => {
+            list.next_id = list.next_id.max(task.id + 1);
+            list.tasks.insert(task.id, task);
+        }
+        Event::Done { id } => {
+            if let Some(task) = list.tasks.get_mut(&id) {
+                task.status = Status::Done;
+            }
+        }
+        Event::Delete { id } => {
+            list.tasks.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn task(id: u64, name: &str) -> Task {
+        Task {
+            id,
+            name: name.to_string(),
+            description: String::new(),
+            date: Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).single().expect("valid date"),
+            category: String::new(),
+            status: Status::Pending,
+            deleted: None,
+            reopened: None,
+        }
+    }
+
+    #[test]
+    fn test_changes_are_appended_and_replayed() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("tasks.jsonl");
+
+        let mut store = EventLogStore::open(&path, Duration::ZERO).expect("open store");
+        let mut list = store.load().expect("load empty log");
+        for (id, name) in [(1, "a"), (2, "b")] {
+            list.tasks.insert(id, task(id, name));
+            store.upsert(&list.tasks[&id], &list).expect("add task");
+        }
+        list.tasks.get_mut(&1).expect("task 1").status = Status::Done;
+        store.upsert(&list.tasks[&1], &list).expect("mark done");
+        store.upsert(&list.tasks[&1], &list).expect("unchanged task");
+        list.tasks.remove(&2);
+        store.delete(2, &list).expect("delete task");
+        drop(store);
+
+        let mut store = EventLogStore::open(&path, Duration::ZERO).expect("reopen store");
+        let events: Vec<Event> = store.records().expect("read records").into_iter().map(|record| record.event).collect();
+        assert_eq!(events, [
+            Event::Add { task: task(1, "a") },
+            Event::Add { task: task(2, "b") },
+            Event::Done { id: 1 },
+            Event::Delete { id: 2 },
+        ]);
+
+        let list = store.load().expect("replay log");
+        assert_eq!(list.tasks.keys().copied().collect::<Vec<_>>(), [1]);
+        assert!(list.tasks[&1].is_done());
+        assert_eq!(list.next_id, 3);
+    }
+
+    #[test]
+    fn test_snapshot_and_truncated_line() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("tasks.jsonl");
+
+        let mut store = EventLogStore::open(&path, Duration::ZERO).expect("open store");
+        let mut list = store.load().expect("load empty log");
+        for id in 1..=SNAPSHOT_INTERVAL as u64 + 1 {
+            list.tasks.insert(id, task(id, &format!("task {id}")));
+            store.upsert(&list.tasks[&id], &list).expect("add task");
+        }
+        drop(store);
+        assert!(dir.path().join("tasks.jsonl.snapshot").exists());
+
+        let mut log = OpenOptions::new().append(true).open(&path).expect("open log");
+        log.write_all(b"{\"at\":\"2024-03-01T09:00:00Z\",\"op\":\"del").expect("write partial line");
+
+        let mut store = EventLogStore::open(&path, Duration::ZERO).expect("reopen store");
+        assert_eq!(store.load().expect("replay log").tasks.len(), SNAPSHOT_INTERVAL + 1);
+        assert_eq!(store.records().expect("read records").len(), SNAPSHOT_INTERVAL + 1);
+        assert!(fs::read_to_string(&path).expect("read log").ends_with('\n'));
+    }
+
+    #[test]
+    fn test_as_of_replays_up_to_a_point_in_time() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("tasks.jsonl");
+        let line = |at: &str, event: Event| {
+            let at = datetime_format::parse(at).expect("valid timestamp");
+            serde_json::to_string(&Record { at, event }).expect("serialize record") + "\n"
+        };
+        let log = [
+            line("2024-02-01T00:00:00Z", Event::Add { task: task(1, "a") }),
+            line("2024-02-15T00:00:00Z", Event::Add { task: task(2, "b") }),
+            // Logs written before the task lifecycle hold a completion flag.
+            r#"{"at":"2024-02-20T00:00:00Z","op":"add","task":{"id":3,"name":"c","description":"","date":"2024-03-01T09:00:00Z","category":"","status":true}}"#.to_string() + "\n",
+            line("2024-03-10T00:00:00Z", Event::Done { id: 1 }),
+            line("2024-03-20T00:00:00Z", Event::Delete { id: 2 }),
+        ]
+        .concat();
+        fs::write(&path, log).expect("write log");
+
+        let store = EventLogStore::open(&path, Duration::ZERO).expect("open store");
+        let march = store.as_of(datetime_format::parse("2024-03-01T00:00:00Z").expect("valid timestamp")).expect("replay");
+        assert_eq!(march.tasks.len(), 3);
+        assert_eq!(march.tasks.values().map(|task| task.status).collect::<Vec<_>>(), [Status::Pending, Status::Pending, Status::Done]);
+        let later = store.as_of(datetime_format::parse("2024-03-31T00:00:00Z").expect("valid timestamp")).expect("replay");
+        assert_eq!(later.tasks.keys().copied().collect::<Vec<_>>(), [1, 3]);
+        assert!(later.tasks[&1].is_done());
+    }
+}
@@ -0,0 +1,304 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::Utc;
+use crate::error::TodoError;
+use crate::models::task::Task;
+use crate::utils::atomic_file;
+use crate::utils::file_lock::FileLock;
+use serde::Serialize;
+use serde_json::Value;
+use super::schema::{self, Migration};
+use super::{TaskList, TaskStore};
+
+/// How long [`JsonFileStore::open`] waits for another process to release
+/// the task file.
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The outcome of [`JsonFileStore::repair`].
+#[derive(Debug)]
+pub struct Repair {
+    /// Number of tasks that could be recovered.
+    pub recovered: usize,
+    /// Where the damaged file was copied before being replaced.
+    pub backup: PathBuf,
+}
+
+/// Stores the whole list as one pretty-printed JSON file, rewritten
+/// atomically on every change.
+#[derive(Debug)]
+pub struct JsonFileStore {
+    path: PathBuf,
+    _lock: FileLock,
+}
+
+impl JsonFileStore {
+    /// Opens the file at `path`, which need not exist yet. The file stays
+    /// locked against other processes until the store is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Locked`] if another process still holds the file
+    /// after `timeout`, or [`TodoError::Storage`] if the lock file cannot be
+    /// created.
+    pub fn open(path: impl Into<PathBuf>, timeout: Duration) -> Result<Self, TodoError> {
+        let path = path.into();
+        let lock = super::lock(&path, timeout)?;
+        Ok(Self { path, _lock: lock })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the task list at `path` without locking or changing the file:
+    /// an outdated file is only migrated in memory. A missing or empty file
+    /// gives an empty list.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the file cannot be read,
+    /// [`TodoError::Corrupt`] if it is not a task list, or the errors of
+    /// [`schema::migrate`].
+    pub fn read(path: &Path) -> Result<TaskList, TodoError> {
+        match read_document(path)? {
+            Some(mut document) => {
+                schema::migrate(&mut document)?;
+                Ok(serde_json::from_value(document)?)
+            }
+            None => Ok(TaskList::default()),
+        }
+    }
+
+    /// Replaces a task file that fails to load with the tasks that can be
+    /// salvaged from it (see [`salvage`](Self::salvage)), after copying it
+    /// aside. Returns `None` if the file loads fine or does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Locked`] if another process holds the file, or
+    /// [`TodoError::Storage`] if it cannot be read, copied or rewritten.
+    pub fn repair(path: impl Into<PathBuf>, timeout: Duration) -> Result<Option<Repair>, TodoError> {
+        let mut store = Self::open(path, timeout)?;
+        let contents = match store.load() {
+            Ok(_) => return Ok(None),
+            Err(TodoError::Corrupt(_)) => fs::read_to_string(&store.path)?,
+            Err(e) => return Err(e),
+        };
+
+        let backup = Self::preserve_corrupt(&store.path)?;
+        let list = Self::salvage(&contents);
+        store.save(&list)?;
+        Ok(Some(Repair {
+            recovered: list.tasks.len(),
+            backup,
+        }))
+    }
+
+    /// Rebuilds a task list from a damaged file, keeping every task entry
+    /// that can still be read. Tasks are renumbered where their IDs are
+    /// missing or clash.
+    #[must_use]
+    pub fn salvage(contents: &str) -> TaskList {
+        let mut salvaged = Vec::new();
+        let mut start = 0;
+        while let Some(offset) = contents[start..].find('{') {
+            let begin = start + offset;
+            let mut values = serde_json::Deserializer::from_str(&contents[begin..]).into_iter::<serde_json::Value>();
+            if let Some(Ok(task)) = values.next().map(|value| value.and_then(serde_json::from_value::<Task>)) {
+                salvaged.push(task);
+                start = begin + values.byte_offset();
+                continue;
+            }
+            start = begin + 1;
+        }
+
+        let mut list = TaskList::default();
+        let mut next_id = salvaged.iter().map(|task| task.id).max().unwrap_or(0) + 1;
+        for mut task in salvaged {
+            if task.id == 0 || list.tasks.contains_key(&task.id) {
+                task.id = next_id;
+                next_id += 1;
+            }
+            list.tasks.insert(task.id, task);
+        }
+        list.next_id = next_id;
+        list
+    }
+
+    /// Copies a file that failed to load to `<path>.<timestamp>.corrupt`
+    /// next to it, so nothing is lost when it is later replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the copy cannot be made.
+    pub fn preserve_corrupt(path: &Path) -> Result<PathBuf, TodoError> {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}.corrupt", Utc::now().format("%Y%m%dT%H%M%S")));
+        let backup = path.with_file_name(name);
+        fs::copy(path, &backup)?;
+        Ok(backup)
+    }
+
+    /// The migrations loading the file would run; empty when it is up to
+    /// date or does not exist yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Corrupt`] if the file is not JSON, or
+    /// [`TodoError::UnsupportedVersion`] if it is newer than this program.
+    pub fn plan(&self) -> Result<MigrationPlan, TodoError> {
+        let version = match read_document(&self.path)? {
+            Some(document) => schema::version(&document)?,
+            None => schema::CURRENT_VERSION,
+        };
+        Ok(MigrationPlan {
+            from: version,
+            steps: schema::plan(version).collect(),
+        })
+    }
+
+    /// Brings the file up to the current schema version (which [`load`]
+    /// also does), returning where the old version was kept, if anything
+    /// had to change.
+    ///
+    /// [`load`]: TaskStore::load
+    ///
+    /// # Errors
+    ///
+    /// See [`plan`](Self::plan); also fails if a step cannot convert the
+    /// data or the file cannot be rewritten.
+    pub fn migrate(&mut self) -> Result<Option<PathBuf>, TodoError> {
+        match read_document(&self.path)? {
+            Some(document) => Ok(self.upgrade(document)?.1),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses `document`, first migrating it (and rewriting the file, after
+    /// copying the original to `<path>.v<version>.bak`) if it is outdated.
+    fn upgrade(&mut self, mut document: Value) -> Result<(TaskList, Option<PathBuf>), TodoError> {
+        let version = schema::version(&document)?;
+        if version == schema::CURRENT_VERSION {
+            return Ok((serde_json::from_value(document)?, None));
+        }
+
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".v{version}.bak"));
+        let backup = self.path.with_file_name(name);
+        fs::copy(&self.path, &backup)?;
+
+        schema::migrate(&mut document)?;
+        let list: TaskList = serde_json::from_value(document)?;
+        self.save(&list)?;
+        Ok((list, Some(backup)))
+    }
+}
+
+/// The parsed file at `path`, or `None` if it is missing or empty.
+fn read_document(path: &Path) -> Result<Option<Value>, TodoError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if contents.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&contents)?))
+}
+
+/// The outcome of [`JsonFileStore::plan`].
+#[derive(Debug)]
+pub struct MigrationPlan {
+    /// The schema version the file is at.
+    pub from: u64,
+    /// The steps up to [`schema::CURRENT_VERSION`], in order.
+    pub steps: Vec<&'static Migration>,
+}
+
+/// The file layout: the task list under a schema version header.
+#[derive(Serialize)]
+struct Document<'a> {
+    version: u64,
+    #[serde(flatten)]
+    list: &'a TaskList,
+}
+
+impl TaskStore for JsonFileStore {
+    /// A missing or empty file gives an empty list, and files written by
+    /// older versions are migrated (see [`migrate`](JsonFileStore::migrate)).
+    /// A file that cannot be parsed is never overwritten.
+    fn load(&mut self) -> Result<TaskList, TodoError> {
+        match read_document(&self.path)? {
+            Some(document) => Ok(self.upgrade(document)?.0),
+            None => Ok(TaskList::default()),
+        }
+    }
+
+    /// Writes the list atomically, keeping the previous version as a `.bak`
+    /// (see [`atomic_file::write`]).
+    fn save(&mut self, list: &TaskList) -> Result<(), TodoError> {
+        let document = Document {
+            version: schema::CURRENT_VERSION,
+            list,
+        };
+        let json = serde_json::to_string_pretty(&document)?;
+        atomic_file::write(&self.path, json.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_migrates_legacy_file_and_keeps_a_backup() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("tasks.json");
+        let legacy = r#"{"tasks":{
+            "b":{"name":"b","description":"","date":"01-01-2021 12:00","category":"","status":false},
+            "a":{"name":"a","description":"","date":"2021-01-01T12:00:00Z","category":"","status":true}
+        }}"#;
+        fs::write(&path, legacy).expect("write file");
+
+        let mut store = JsonFileStore::open(&path, Duration::ZERO).expect("open store");
+        assert_eq!(store.plan().expect("plan migration").steps.len(), 5);
+        let list = store.load().expect("legacy file should load");
+        let id = |name: &str| list.tasks.values().find(|task| task.name == name).map(|task| task.id);
+        assert_eq!(id("a"), Some(1));
+        assert_eq!(id("b"), Some(2));
+        assert!(list.tasks[&1].is_done());
+
+        assert_eq!(fs::read_to_string(dir.path().join("tasks.json.v0.bak")).expect("read backup"), legacy);
+        assert!(fs::read_to_string(&path).expect("read file").contains("\"version\": 5"));
+        assert!(store.plan().expect("plan migration").steps.is_empty());
+    }
+
+    #[test]
+    fn test_salvage_keeps_readable_tasks() {
+        let json = r#"{"tasks":{
+            "1":{"id":1,"name":"a","description":"","date":"2021-01-01T12:00:00Z","category":"","status":false},
+            "2":{"id":2,"name":"b","description":"","date":"not a date","category":"","status":false},
+            "3":{"id":1,"name":"c","description":"","date":"2021-01-03T12:00:00Z","category":"","status":true},
+            "4":{"id":4,"name":"d","description":"","da"#;
+        let list = JsonFileStore::salvage(json);
+        let names: Vec<(u64, &str)> = list.tasks.values().map(|task| (task.id, task.name.as_str())).collect();
+        assert_eq!(names, vec![(1, "a"), (2, "c")]);
+        assert_eq!(list.next_id, 3);
+    }
+
+    #[test]
+    fn test_corrupt_file_is_not_overwritten() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("tasks.json");
+        fs::write(&path, "not json").expect("write file");
+
+        let mut store = JsonFileStore::open(&path, Duration::ZERO).expect("open store");
+        assert!(matches!(store.load(), Err(TodoError::Corrupt(_))));
+        assert_eq!(fs::read_to_string(&path).expect("read file"), "not json");
+    }
+}
@@ -0,0 +1,39 @@
+use crate::error::TodoError;
+use crate::models::task::Task;
+use super::{TaskList, TaskStore};
+
+/// Keeps the list in memory only; useful for tests and scratch lists.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    list: TaskList,
+}
+
+impl MemoryStore {
+    /// A store that starts out holding `list`.
+    #[must_use]
+    pub const fn new(list: TaskList) -> Self {
+        Self { list }
+    }
+}
+
+impl TaskStore for MemoryStore {
+    fn load(&mut self) -> Result<TaskList, TodoError> {
+        Ok(self.list.clone())
+    }
+
+    fn save(&mut self, list: &TaskList) -> Result<(), TodoError> {
+        self.list = list.clone();
+        Ok(())
+    }
+
+    fn upsert(&mut self, task: &Task, list: &TaskList) -> Result<(), TodoError> {
+        self.list.tasks.insert(task.id, task.clone());
+        self.list.next_id = list.next_id;
+        Ok(())
+    }
+
+    fn delete(&mut self, id: u64, _list: &TaskList) -> Result<(), TodoError> {
+        self.list.tasks.remove(&id);
+        Ok(())
+    }
+}
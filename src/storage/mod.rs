@@ -0,0 +1,157 @@
+mod event_log;
+mod json_file;
+mod memory;
+pub mod schema;
+mod sqlite;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::error::TodoError;
+use crate::models::task::Task;
+use crate::query::ast::Expr;
+use crate::utils::file_lock::FileLock;
+
+pub use event_log::{Event, EventLogStore, Record, SNAPSHOT_INTERVAL};
+pub use json_file::{JsonFileStore, MigrationPlan, Repair, DEFAULT_LOCK_TIMEOUT};
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+/// Everything a store persists: the tasks and the next ID to hand out.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TaskList {
+    pub tasks: BTreeMap<u64, Task>,
+    #[serde(default)]
+    pub next_id: u64,
+}
+
+/// Where a [`TodoListService`](crate::TodoListService) keeps its tasks.
+///
+/// `upsert` and `delete` are called after every change with the list as it
+/// now stands; stores that cannot write a single task just save the whole
+/// list, which is what the default implementations do.
+pub trait TaskStore: fmt::Debug {
+    /// Reads the stored list.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] or [`TodoError::Corrupt`] if the
+    /// stored data cannot be read.
+    fn load(&mut self) -> Result<TaskList, TodoError>;
+
+    /// Replaces the stored list with `list`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the list cannot be written.
+    fn save(&mut self, list: &TaskList) -> Result<(), TodoError>;
+
+    /// Records that `task` was added or changed.
+    ///
+    /// # Errors
+    ///
+    /// See [`save`](Self::save).
+    fn upsert(&mut self, task: &Task, list: &TaskList) -> Result<(), TodoError> {
+        let _ = task;
+        self.save(list)
+    }
+
+    /// Records that the task with `id` was removed.
+    ///
+    /// # Errors
+    ///
+    /// See [`save`](Self::save).
+    fn delete(&mut self, id: u64, list: &TaskList) -> Result<(), TodoError> {
+        let _ = id;
+        self.save(list)
+    }
+
+    /// The IDs of the tasks that may match `filter`, for stores that can
+    /// narrow a `select` down before it is run over the loaded list. The
+    /// filter is still applied to the result, so a superset is fine; `None`
+    /// means every task has to be checked.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Storage`] if the store cannot be queried.
+    fn candidates(&self, filter: &Expr) -> Result<Option<BTreeSet<u64>>, TodoError> {
+        let _ = filter;
+        Ok(None)
+    }
+
+    /// The list as it was at `at`, for stores that keep their history.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Unsupported`] for stores that only keep the
+    /// current list (the default), or [`TodoError::Storage`] if the history
+    /// cannot be read.
+    fn as_of(&self, at: DateTime<Utc>) -> Result<TaskList, TodoError> {
+        let _ = at;
+        Err(TodoError::Unsupported(
+            "only the event log store keeps a history of changes".to_string(),
+        ))
+    }
+}
+
+/// Takes the cross-process lock for a store at `path`.
+fn lock(path: &Path, timeout: Duration) -> Result<FileLock, TodoError> {
+    FileLock::acquire(path, timeout).map_err(|e| match e.kind() {
+        io::ErrorKind::TimedOut => TodoError::Locked {
+            path: path.to_path_buf(),
+            timeout,
+        },
+        _ => TodoError::Storage(e),
+    })
+}
+
+/// The available [`TaskStore`] implementations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StoreKind {
+    /// A JSON file, see [`JsonFileStore`].
+    #[default]
+    Json,
+    /// Nothing is persisted, see [`MemoryStore`].
+    Memory,
+    /// A SQLite database, see [`SqliteStore`].
+    Sqlite,
+    /// An append-only log of changes, see [`EventLogStore`].
+    Events,
+}
+
+impl StoreKind {
+    /// Opens a store of this kind at `path`, waiting up to `lock_timeout`
+    /// for stores that lock it.
+    ///
+    /// # Errors
+    ///
+    /// See [`JsonFileStore::open`], [`SqliteStore::open`] and
+    /// [`EventLogStore::open`].
+    pub fn open(self, path: &Path, lock_timeout: Duration) -> Result<Box<dyn TaskStore>, TodoError> {
+        Ok(match self {
+            Self::Json => Box::new(JsonFileStore::open(path, lock_timeout)?),
+            Self::Memory => Box::<MemoryStore>::default(),
+            Self::Sqlite => Box::new(SqliteStore::open(path, lock_timeout)?),
+            Self::Events => Box::new(EventLogStore::open(path, lock_timeout)?),
+        })
+    }
+}
+
+impl FromStr for StoreKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.trim().to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "memory" => Ok(Self::Memory),
+            "sqlite" => Ok(Self::Sqlite),
+            "events" => Ok(Self::Events),
+            _ => Err(format!("Unknown store `{name}`, expected json, memory, sqlite or events")),
+        }
+    }
+}
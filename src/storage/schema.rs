@@ -0,0 +1,206 @@
+use serde_json::{Map, Value};
+use crate::error::TodoError;
+use crate::utils::datetime_format;
+
+/// The schema version written into every task file.
+pub const CURRENT_VERSION: u64 = 5;
+
+/// One upgrade step, from version `from` to `from + 1`.
+#[derive(Debug)]
+pub struct Migration {
+    pub from: u64,
+    pub description: &'static str,
+    apply: fn(&mut Map<String, Value>) -> Result<(), String>,
+}
+
+/// Files without a `version` field are version 0, whatever they contain, so
+/// each step must leave data it does not need to change alone.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "store dates as RFC 3339 timestamps instead of DD-MM-YYYY HH:MM",
+        apply: rfc3339_dates,
+    },
+    Migration {
+        from: 1,
+        description: "give every task a numeric ID and key tasks by it instead of by name",
+        apply: task_ids,
+    },
+    Migration {
+        from: 2,
+        description: "allow tasks to be moved to the trash (an optional `deleted` timestamp)",
+        // Nothing to convert: existing tasks are simply not trashed. The
+        // version bump keeps older programs, which would drop the field, away
+        // from files that may contain trashed tasks.
+        apply: |_| Ok(()),
+    },
+    Migration {
+        from: 3,
+        description: "replace the `true`/`false` completion flag with a named status",
+        apply: status_names,
+    },
+    Migration {
+        from: 4,
+        description: "record when a task was reopened (an optional `reopened` timestamp)",
+        // Like the trash: nothing to convert, but older programs must not
+        // drop the field.
+        apply: |_| Ok(()),
+    },
+];
+
+/// The schema version of a parsed task file.
+///
+/// # Errors
+///
+/// Returns [`TodoError::UnsupportedVersion`] for a file written by a newer
+/// version of this program.
+pub fn version(document: &Value) -> Result<u64, TodoError> {
+    let version = document.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > CURRENT_VERSION {
+        return Err(TodoError::UnsupportedVersion {
+            found: version,
+            supported: CURRENT_VERSION,
+        });
+    }
+    Ok(version)
+}
+
+/// The steps that bring a file at `version` up to [`CURRENT_VERSION`].
+pub fn plan(version: u64) -> impl Iterator<Item = &'static Migration> {
+    MIGRATIONS.iter().filter(move |migration| migration.from >= version)
+}
+
+/// Upgrades `document` in place, one step at a time, and stamps it with
+/// [`CURRENT_VERSION`].
+///
+/// # Errors
+///
+/// Returns [`TodoError::UnsupportedVersion`] for newer files, or
+/// [`TodoError::Migration`] if a step cannot make sense of the data.
+pub fn migrate(document: &mut Value) -> Result<(), TodoError> {
+    let version = version(document)?;
+    let Some(fields) = document.as_object_mut() else {
+        return Err(TodoError::Migration {
+            from: version,
+            message: "the file does not contain a JSON object".to_string(),
+        });
+    };
+    for migration in plan(version) {
+        (migration.apply)(fields).map_err(|message| TodoError::Migration {
+            from: migration.from,
+            message,
+        })?;
+        fields.insert("version".to_string(), Value::from(migration.from + 1));
+    }
+    fields.insert("version".to_string(), Value::from(CURRENT_VERSION));
+    Ok(())
+}
+
+fn tasks_mut(fields: &mut Map<String, Value>) -> Result<&mut Map<String, Value>, String> {
+    fields
+        .entry("tasks")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .ok_or_else(|| "`tasks` is not an object".to_string())
+}
+
+/// 0 → 1: dates used to be written as naive `DD-MM-YYYY HH:MM` in UTC.
+fn rfc3339_dates(fields: &mut Map<String, Value>) -> Result<(), String> {
+    for (key, task) in tasks_mut(fields)? {
+        let Some(date) = task.get_mut("date") else {
+            continue;
+        };
+        let Some(text) = date.as_str() else {
+            return Err(format!("task `{key}` has a date that is not a string"));
+        };
+        let parsed = datetime_format::parse(text).ok_or_else(|| format!("task `{key}` has an invalid date `{text}`"))?;
+        *date = Value::from(datetime_format::format(parsed));
+    }
+    Ok(())
+}
+
+/// 1 → 2: tasks used to be keyed by name, without an `id` field.
+fn task_ids(fields: &mut Map<String, Value>) -> Result<(), String> {
+    let tasks = std::mem::take(tasks_mut(fields)?);
+    let id_of = |task: &Value| task.get("id").and_then(Value::as_u64).filter(|id| *id > 0);
+    let mut next_id = tasks.values().filter_map(id_of).max().unwrap_or(0) + 1;
+
+    let mut by_id = Map::new();
+    for (key, mut task) in tasks {
+        let id = match id_of(&task) {
+            Some(id) if !by_id.contains_key(&id.to_string()) => id,
+            _ => {
+                let id = next_id;
+                next_id += 1;
+                id
+            }
+        };
+        task.as_object_mut()
+            .ok_or_else(|| format!("task `{key}` is not an object"))?
+            .insert("id".to_string(), Value::from(id));
+        by_id.insert(id.to_string(), task);
+    }
+
+    let stored_next_id = fields.get("next_id").and_then(Value::as_u64).unwrap_or(0);
+    fields.insert("tasks".to_string(), Value::Object(by_id));
+    fields.insert("next_id".to_string(), Value::from(stored_next_id.max(next_id)));
+    Ok(())
+}
+
+/// 3 → 4: `status` used to be `true` for done tasks and `false` otherwise.
+fn status_names(fields: &mut Map<String, Value>) -> Result<(), String> {
+    for (key, task) in tasks_mut(fields)? {
+        let Some(status) = task.get_mut("status") else {
+            continue;
+        };
+        match status {
+            Value::Bool(done) => *status = Value::from(if *done { "done" } else { "pending" }),
+            Value::String(_) => {}
+            _ => return Err(format!("task `{key}` has a status that is neither a flag nor a name")),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_migrates_legacy_file_step_by_step() {
+        let mut document = json!({"tasks": {
+            "b": {"name": "b", "description": "", "date": "01-01-2021 12:00", "category": "", "status": false},
+            "a": {"name": "a", "description": "", "date": "2021-01-02T12:00:00Z", "category": "", "status": true}
+        }});
+        assert_eq!(plan(version(&document).expect("supported version")).count(), 5);
+
+        migrate(&mut document).expect("legacy file migrates");
+        assert_eq!(document["version"], json!(CURRENT_VERSION));
+        assert_eq!(document["next_id"], json!(3));
+        assert_eq!(document["tasks"]["1"]["name"], json!("a"));
+        assert_eq!(document["tasks"]["2"]["date"], json!("2021-01-01T12:00:00Z"));
+        assert_eq!(document["tasks"]["1"]["status"], json!("done"));
+        assert_eq!(document["tasks"]["2"]["status"], json!("pending"));
+        assert_eq!(plan(version(&document).expect("supported version")).count(), 0);
+    }
+
+    #[test]
+    fn test_keeps_existing_ids() {
+        let mut document = json!({"tasks": {
+            "4": {"id": 4, "name": "x", "description": "", "date": "2021-01-02T12:00:00Z", "category": "", "status": true}
+        }, "next_id": 7});
+        migrate(&mut document).expect("file migrates");
+        assert_eq!(document["tasks"]["4"]["id"], json!(4));
+        assert_eq!(document["next_id"], json!(7));
+    }
+
+    #[test]
+    fn test_rejects_newer_and_broken_files() {
+        let newer = json!({"version": CURRENT_VERSION + 1, "tasks": {}});
+        assert!(matches!(version(&newer), Err(TodoError::UnsupportedVersion { .. })));
+
+        let mut broken = json!({"tasks": {"a": {"name": "a", "date": "someday"}}});
+        assert!(matches!(migrate(&mut broken), Err(TodoError::Migration { from: 0, .. })));
+    }
+}
@@ -0,0 +1,447 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use crate::error::TodoError;
+use crate::models::status::Status;
+use crate::models::task::Task;
+use crate::query::ast::{Condition, Expr, Field, Literal, Operator};
+use crate::utils::datetime_format;
+use crate::utils::file_lock::FileLock;
+use super::{TaskList, TaskStore};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS tasks (
+        id          INTEGER PRIMARY KEY,
+        name        TEXT NOT NULL,
+        description TEXT NOT NULL,
+        date        TEXT NOT NULL,
+        category    TEXT NOT NULL,
+        status      TEXT NOT NULL,
+        deleted     TEXT,
+        reopened    TEXT
+    );
+    CREATE INDEX IF NOT EXISTS tasks_date ON tasks (date);
+    CREATE INDEX IF NOT EXISTS tasks_category ON tasks (category);
+    CREATE INDEX IF NOT EXISTS tasks_status ON tasks (status);
+    CREATE TABLE IF NOT EXISTS meta (
+        key   TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+";
+
+/// Stores tasks as rows of a SQLite database, so a change only writes the
+/// task it touches.
+///
+/// [`load`](TaskStore::load) still reads every row; the indexes on date,
+/// category and status only speed up [`candidates`](TaskStore::candidates).
+#[derive(Debug)]
+pub struct SqliteStore {
+    path: PathBuf,
+    connection: Connection,
+    _lock: FileLock,
+}
+
+impl SqliteStore {
+    /// Opens (creating if needed) the database at `path`. Like
+    /// [`JsonFileStore`](super::JsonFileStore) it locks `<path>.lock` until
+    /// dropped, so read-modify-write cycles of concurrent processes do not
+    /// interleave.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TodoError::Locked`] if another process holds the database
+    /// after `timeout`, or [`TodoError::Storage`] if it cannot be opened.
+    pub fn open(path: impl Into<PathBuf>, timeout: Duration) -> Result<Self, TodoError> {
+        let path = path.into();
+        let lock = super::lock(&path, timeout)?;
+        let connection = Connection::open(&path)?;
+        connection.busy_timeout(timeout)?;
+        connection.execute_batch(SCHEMA)?;
+        add_missing_columns(&connection)?;
+        name_statuses(&connection)?;
+        truncate_dates(&connection)?;
+        Ok(Self { path, connection, _lock: lock })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+}
+
+/// Databases created before tasks could be trashed or reopened lack the
+/// `deleted` and `reopened` columns.
+fn add_missing_columns(connection: &Connection) -> Result<(), TodoError> {
+    for column in ["deleted", "reopened"] {
+        let exists: bool = connection.query_row(
+            "SELECT EXISTS (SELECT 1 FROM pragma_table_info('tasks') WHERE name = ?1)",
+            [column],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            connection.execute(&format!("ALTER TABLE tasks ADD COLUMN {column} TEXT"), [])?;
+        }
+    }
+    Ok(())
+}
+
+/// Databases created before the task lifecycle store `status` as 0 or 1.
+fn name_statuses(connection: &Connection) -> Result<(), TodoError> {
+    connection.execute(
+        "UPDATE tasks SET status = CASE status WHEN 0 THEN 'pending' ELSE 'done' END
+         WHERE typeof(status) = 'integer'",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Databases written before dates were stored to the second hold them with
+/// nanoseconds, which neither compare equal to nor sort consistently with
+/// the whole-second form the journal and history use.
+fn truncate_dates(connection: &Connection) -> Result<(), TodoError> {
+    for column in ["date", "deleted", "reopened"] {
+        connection.execute(
+            &format!("UPDATE tasks SET {column} = substr({column}, 1, 19) || 'Z' WHERE length({column}) > 20"),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+fn insert(connection: &Connection, task: &Task) -> Result<(), TodoError> {
+    connection.execute(
+        "INSERT OR REPLACE INTO tasks (id, name, description, date, category, status, deleted, reopened)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            sql_id(task.id)?,
+            task.name,
+            task.description,
+            sql_date(task.date),
+            task.category,
+            task.status.as_str(),
+            task.deleted.map(sql_date),
+            task.reopened.map(sql_date),
+        ],
+    )?;
+    Ok(())
+}
+
+fn set_next_id(connection: &Connection, next_id: u64) -> Result<(), TodoError> {
+    connection.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES ('next_id', ?1)",
+        params![sql_id(next_id)?],
+    )?;
+    Ok(())
+}
+
+impl TaskStore for SqliteStore {
+    fn load(&mut self) -> Result<TaskList, TodoError> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, name, description, date, category, status, deleted, reopened FROM tasks ORDER BY id",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, Option<String>>(6)?,
+                row.get::<_, Option<String>>(7)?,
+            ))
+        })?;
+
+        let mut list = TaskList::default();
+        for row in rows {
+            let (id, name, description, date, category, status, deleted, reopened) = row?;
+            let id = u64::try_from(id).map_err(|_| corrupt(format!("invalid task id {id}")))?;
+            let date = parse_sql_date(&date, id)?;
+            let status: Status = status.parse().map_err(|e| corrupt(format!("{e} for task {id}")))?;
+            let deleted = deleted.map(|deleted| parse_sql_date(&deleted, id)).transpose()?;
+            let reopened = reopened.map(|reopened| parse_sql_date(&reopened, id)).transpose()?;
+            list.tasks.insert(id, Task { id, name, description, date, category, status, deleted, reopened });
+        }
+
+        let next_id: Option<i64> = self.connection
+            .query_row("SELECT value FROM meta WHERE key = 'next_id'", [], |row| row.get(0))
+            .optional()?;
+        list.next_id = next_id.and_then(|id| u64::try_from(id).ok()).unwrap_or(0);
+        Ok(list)
+    }
+
+    fn save(&mut self, list: &TaskList) -> Result<(), TodoError> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute("DELETE FROM tasks", [])?;
+        for task in list.tasks.values() {
+            insert(&transaction, task)?;
+        }
+        set_next_id(&transaction, list.next_id)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn upsert(&mut self, task: &Task, list: &TaskList) -> Result<(), TodoError> {
+        let transaction = self.connection.transaction()?;
+        insert(&transaction, task)?;
+        set_next_id(&transaction, list.next_id)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn delete(&mut self, id: u64, _list: &TaskList) -> Result<(), TodoError> {
+        self.connection.execute("DELETE FROM tasks WHERE id = ?1", params![sql_id(id)?])?;
+        Ok(())
+    }
+
+    fn candidates(&self, filter: &Expr) -> Result<Option<BTreeSet<u64>>, TodoError> {
+        let Some((clause, values)) = narrowing(filter) else {
+            return Ok(None);
+        };
+        let mut statement = self.connection.prepare(&format!("SELECT id FROM tasks WHERE {clause}"))?;
+        let ids = statement
+            .query_map(params_from_iter(values), |row| row.get::<_, i64>(0))?
+            .filter_map(|id| id.map(|id| u64::try_from(id).ok()).transpose())
+            .collect::<Result<BTreeSet<u64>, _>>()?;
+        Ok(Some(ids))
+    }
+}
+
+type Sql = (String, Vec<Value>);
+
+/// A clause selecting a superset of the tasks `expr` matches. Conditions
+/// that have no SQL equivalent are dropped from `and` chains; anything else
+/// must translate exactly.
+fn narrowing(expr: &Expr) -> Option<Sql> {
+    match expr {
+        Expr::And(left, right) => match (narrowing(left), narrowing(right)) {
+            (Some(left), Some(right)) => Some(combine("AND", left, right)),
+            (Some(only), None) | (None, Some(only)) => Some(only),
+            (None, None) => None,
+        },
+        _ => exact(expr),
+    }
+}
+
+/// A clause selecting exactly the tasks `expr` matches, if there is one.
+fn exact(expr: &Expr) -> Option<Sql> {
+    match expr {
+        Expr::Condition(condition) => condition_sql(condition),
+        Expr::And(left, right) => Some(combine("AND", exact(left)?, exact(right)?)),
+        Expr::Or(left, right) => Some(combine("OR", exact(left)?, exact(right)?)),
+        Expr::Not(inner) => {
+            let (clause, values) = exact(inner)?;
+            Some((format!("NOT ({clause})"), values))
+        }
+        Expr::ChangedSince(_) => None,
+    }
+}
+
+fn combine(operator: &str, (left, mut values): Sql, (right, right_values): Sql) -> Sql {
+    values.extend(right_values);
+    (format!("({left}) {operator} ({right})"), values)
+}
+
+/// Mirrors `Condition::matches`: text compares byte-wise and case-sensitively,
+/// which is SQLite's default `BINARY` collation.
+fn condition_sql(condition: &Condition) -> Option<Sql> {
+    let column = condition.field.as_str();
+    if let Literal::List(items) = &condition.value {
+        let values = items.iter()
+            .map(|item| value(condition.field, item))
+            .collect::<Option<Vec<Value>>>()?;
+        let placeholders = vec!["?"; values.len()].join(", ");
+        let operator = if condition.operator == Operator::Ne { "NOT IN" } else { "IN" };
+        return Some((format!("{column} {operator} ({placeholders})"), values));
+    }
+
+    let value = value(condition.field, &condition.value)?;
+    let clause = match condition.operator {
+        Operator::Lt | Operator::Le | Operator::Eq | Operator::Ge | Operator::Gt => {
+            format!("{column} {} ?", condition.operator.as_str())
+        }
+        Operator::Ne => format!("{column} <> ?"),
+        Operator::Like => format!("instr({column}, ?) > 0"),
+        Operator::NotLike => format!("instr({column}, ?) = 0"),
+        Operator::StartsWith => {
+            return Some((format!("substr({column}, 1, length(?)) = ?"), vec![value.clone(), value]));
+        }
+        Operator::EndsWith | Operator::In | Operator::Matches => return None,
+    };
+    Some((clause, vec![value]))
+}
+
+/// The SQL value `literal` is compared against in `field`'s column, or
+/// `None` if the pair never matches in memory either (so it is left there).
+fn value(field: Field, literal: &Literal) -> Option<Value> {
+    match (field, literal) {
+        (Field::Name | Field::Description | Field::Category, Literal::Text(text)) => Some(Value::Text(text.clone())),
+        (Field::Date, Literal::Date(date)) => Some(Value::Text(sql_date(*date))),
+        (Field::Status, Literal::Status(status)) => Some(Value::Text(status.as_str().to_string())),
+        (Field::Id, Literal::Number(id)) => i64::try_from(*id).ok().map(Value::Integer),
+        _ => None,
+    }
+}
+
+/// RFC 3339 in UTC to the second, like everywhere else dates are stored.
+/// The form is fixed-width, so text order is time order.
+fn sql_date(date: DateTime<Utc>) -> String {
+    datetime_format::format(date)
+}
+
+fn parse_sql_date(date: &str, id: u64) -> Result<DateTime<Utc>, TodoError> {
+    DateTime::parse_from_rfc3339(date)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|e| corrupt(format!("invalid date `{date}` for task {id}: {e}")))
+}
+
+fn sql_id(id: u64) -> Result<i64, TodoError> {
+    i64::try_from(id).map_err(|_| corrupt(format!("task id {id} is too large")))
+}
+
+fn corrupt(message: String) -> TodoError {
+    TodoError::Storage(std::io::Error::new(std::io::ErrorKind::InvalidData, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query;
+    use crate::utils::date;
+
+    fn task(id: u64, name: &str, date: &str, category: &str, status: Status) -> Task {
+        Task {
+            id,
+            name: name.to_string(),
+            description: format!("{name} notes"),
+            date: date::parse(date).expect("valid date"),
+            category: category.to_string(),
+            status,
+            deleted: None,
+            reopened: None,
+        }
+    }
+
+    fn store_with_tasks(dir: &Path) -> (SqliteStore, TaskList) {
+        let mut store = SqliteStore::open(dir.join("tasks.sqlite"), Duration::ZERO).expect("open store");
+        let mut list = TaskList::default();
+        for task in [
+            task(1, "OPS-1 deploy", "01-01-2023 10:00", "ops", Status::Pending),
+            task(2, "DEV-2 review", "01-06-2023 10:00", "dev", Status::Done),
+            task(3, "ops backlog", "15-03-2023 08:30", "Ops", Status::Blocked),
+            task(4, "Ünïcode", "01-01-2024 00:00", "home", Status::Done),
+        ] {
+            list.tasks.insert(task.id, task);
+        }
+        list.next_id = 5;
+        store.save(&list).expect("save list");
+        (store, list)
+    }
+
+    #[test]
+    fn test_save_upsert_delete_round_trip() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let (mut store, mut list) = store_with_tasks(dir.path());
+
+        let mut changed = list.tasks[&2].clone();
+        changed.status = Status::InProgress;
+        changed.deleted = Some(list.tasks[&1].date);
+        changed.reopened = Some(list.tasks[&4].date);
+        list.tasks.insert(2, changed.clone());
+        list.next_id = 6;
+        store.upsert(&changed, &list).expect("upsert task");
+        list.tasks.remove(&3);
+        store.delete(3, &list).expect("delete task");
+
+        let loaded = store.load().expect("load list");
+        assert_eq!(loaded.next_id, 6);
+        assert_eq!(loaded.tasks.keys().copied().collect::<Vec<_>>(), vec![1, 2, 4]);
+        assert_eq!(loaded.tasks[&2].status, Status::InProgress);
+        assert_eq!(loaded.tasks[&2].deleted, changed.deleted);
+        assert_eq!(loaded.tasks[&2].reopened, changed.reopened);
+        assert_eq!(loaded.tasks[&1].deleted, None);
+        assert_eq!(loaded.tasks[&4].name, "Ünïcode");
+        assert_eq!(loaded.tasks[&1].date, list.tasks[&1].date);
+    }
+
+    #[test]
+    fn test_failed_writes_change_nothing() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let (mut store, mut list) = store_with_tasks(dir.path());
+
+        let added = task(5, "added", "01-02-2024 10:00", "ops", Status::Pending);
+        list.tasks.insert(5, added.clone());
+        list.next_id = u64::MAX;
+        assert!(store.upsert(&added, &list).is_err());
+        list.tasks.clear();
+        assert!(store.save(&list).is_err());
+
+        let loaded = store.load().expect("load list");
+        assert_eq!(loaded.tasks.keys().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(loaded.next_id, 5);
+    }
+
+    #[test]
+    fn test_open_upgrades_older_databases() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("tasks.sqlite");
+        Connection::open(&path).expect("create database").execute_batch(
+            "CREATE TABLE tasks (id INTEGER PRIMARY KEY, name TEXT NOT NULL, description TEXT NOT NULL,
+                                 date TEXT NOT NULL, category TEXT NOT NULL, status INTEGER NOT NULL);
+             INSERT INTO tasks VALUES (1, 'old', '', '2023-01-01T10:00:00.250000000Z', 'ops', 0),
+                                      (2, 'finished', '', '2023-01-01T10:00:00.000000000Z', 'ops', 1);",
+        ).expect("create old schema");
+
+        let mut store = SqliteStore::open(&path, Duration::ZERO).expect("open store");
+        let loaded = store.load().expect("load list");
+        assert_eq!(loaded.tasks[&1].name, "old");
+        assert_eq!(loaded.tasks[&1].deleted, None);
+        assert_eq!(loaded.tasks[&1].reopened, None);
+        assert_eq!(loaded.tasks[&1].status, Status::Pending);
+        assert_eq!(loaded.tasks[&2].status, Status::Done);
+        assert_eq!(loaded.tasks[&1].date, loaded.tasks[&2].date);
+        let stored: String = store.connection
+            .query_row("SELECT date FROM tasks WHERE id = 1", [], |row| row.get(0))
+            .expect("read date");
+        assert_eq!(stored, "2023-01-01T10:00:00Z");
+    }
+
+    #[test]
+    fn test_candidates_agree_with_in_memory_filter() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let (store, list) = store_with_tasks(dir.path());
+
+        for input in [
+            "category = ops",
+            "category != ops and status = done",
+            "date >= 01-03-2023 00:00 and date < 01-01-2024 00:00",
+            "name like OPS or not status = pending",
+            "status in (blocked, done) and status != pending",
+            "status = off or status != open",
+            "status in (open, done) and not status = off",
+            "name starts with ops",
+            "name ends with review",
+            "name matches /^ops/i and category in (ops, Ops)",
+            "not (name matches /review/ or id = 1)",
+            "id in (1, 4) order by id desc limit 1",
+            "description not like notes",
+        ] {
+            let query = query::parse(input).expect("query should parse");
+            let filter = query.filter.as_ref().expect("query has a filter");
+            let expected: Vec<u64> = query.apply(list.tasks.values()).iter().map(|task| task.id).collect();
+            let candidates = store.candidates(filter)
+                .expect("query store")
+                .unwrap_or_else(|| list.tasks.keys().copied().collect());
+            let narrowed: Vec<u64> = query.apply(candidates.iter().map(|id| &list.tasks[id])).iter().map(|task| task.id).collect();
+            assert_eq!(narrowed, expected, "{input}");
+        }
+
+        let exact = query::parse("category = ops").expect("query should parse");
+        let ids = store.candidates(exact.filter.as_ref().expect("query has a filter")).expect("query store");
+        assert_eq!(ids, Some(BTreeSet::from([1])));
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Replaces the contents of `path` so that readers only ever see the old or
+/// the new version.
+///
+/// The data goes to a temporary file in the same directory, is flushed to
+/// disk and then renamed over `path`; the previous version is kept as
+/// `<path>.bak`.
+///
+/// # Errors
+///
+/// Returns the first I/O error; `path` is left as it was in that case.
+pub fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp = sibling(path, &format!(".{}.tmp", process::id()));
+    let result = write_synced(&temp, contents).and_then(|()| {
+        if path.exists() {
+            keep_backup(path)?;
+        }
+        fs::rename(&temp, path)?;
+        sync_parent(path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// The backup written by [`write`] for `path`.
+#[must_use]
+pub fn backup_path(path: &Path) -> PathBuf {
+    sibling(path, ".bak")
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Points `<path>.bak` at the current version. A hard link is used where the
+/// file system allows it, so the backup is never half-written either.
+fn keep_backup(path: &Path) -> io::Result<()> {
+    let backup = backup_path(path);
+    match fs::remove_file(&backup) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    if fs::hard_link(path, &backup).is_err() {
+        fs::copy(path, &backup)?;
+    }
+    Ok(())
+}
+
+/// Makes the rename itself durable.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_replaces_file_and_keeps_backup() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("db.json");
+
+        write(&path, b"first").expect("first write");
+        assert!(!backup_path(&path).exists());
+        write(&path, b"second").expect("second write");
+
+        assert_eq!(fs::read_to_string(&path).expect("read file"), "second");
+        assert_eq!(fs::read_to_string(backup_path(&path)).expect("read backup"), "first");
+        let leftovers = fs::read_dir(dir.path())
+            .expect("list dir")
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+}
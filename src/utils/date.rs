@@ -1,11 +1,617 @@
-use chrono::{DateTime, Utc, NaiveDateTime};
+use chrono::{DateTime, Datelike, Days, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, SubsecRound, Utc, Weekday};
+use std::env;
+use super::timezone::Zone;
 
+const FORMAT: &str = "%d-%m-%Y %H:%M";
+
+/// The current time, to the second like every stored date. Setting
+/// `TODO_NOW` (in `DD-MM-YYYY HH:MM` format, in the configured zone) pins the
+/// clock, which keeps relative dates reproducible in tests.
+pub fn now() -> DateTime<Utc> {
+    env::var("TODO_NOW")
+        .ok()
+        .and_then(|value| NaiveDateTime::parse_from_str(&value, FORMAT).ok())
+        .and_then(|ndt| Zone::current().resolve(ndt).ok())
+        .unwrap_or_else(Utc::now)
+        .trunc_subsecs(0)
+}
+
+/// Parses a date entered by the user in the configured zone (see
+/// [`Zone::current`]).
+///
+/// # Errors
+///
+/// See [`parse_in`].
 pub fn parse(input: &str) -> Result<DateTime<Utc>, String> {
+    parse_in(input, now(), Zone::current())
+}
+
+/// Parses an RFC 3339 timestamp, an absolute `DD-MM-YYYY HH:MM` date or a
+/// relative or natural-language expression resolved against `now`.
+///
+/// Wall-clock values ("today", "5pm", absolute dates) are interpreted in
+/// `zone`:
+///
+/// ```text
+/// expression := 'in' ( amount unit [ 'and' ] )+
+///             | time
+///             | base [ [ 'at' ] time ] ( ('+' | '-') amount unit )*
+/// base       := DD-MM-YYYY HH:MM | now | today | tomorrow | yesterday
+///             | [ next | last ] <weekday> | (next | last) (week | month)
+///             | (start | end) of (day | week | month) | eod | eow | eom
+///             | [ the ] <ordinal> [ of (this month | next month | <month>) ]
+/// time       := HH:MM | H[:MM](am|pm) | noon | midnight
+/// unit       := m | min | h | d | w | mo (and their long forms)
+/// ```
+///
+/// The result is truncated to whole seconds, as dates are stored.
+///
+/// Ambiguity policy:
+/// - the strict format is always day-first and always wins;
+/// - a bare weekday, day of month (`3rd`), `<ordinal> of <month>` or time
+///   (`5pm`) means its nearest occurrence that is not in the past, so `fri`
+///   on a Friday afternoon is next week's Friday, while `fri 5pm` on Friday
+///   morning is today;
+/// - `next <weekday>` is never today, `last <weekday>` is never today;
+/// - dates without a time are at 00:00, except `end of ...`/`eod`/`eow`/`eom`
+///   which are at 23:59;
+/// - an hour without `am`/`pm` or minutes (`at 5`) is rejected.
+///
+/// # Errors
+///
+/// Returns a message describing why `input` is not a date, or that the time
+/// does not exist in `zone`.
+pub fn parse_in(input: &str, now: DateTime<Utc>, zone: Zone) -> Result<DateTime<Utc>, String> {
     let date_str = &input.replace(['"', '\''], "");
-    
+
+    if let Ok(parsed_date) = DateTime::parse_from_rfc3339(date_str.trim()) {
+        return Ok(parsed_date.with_timezone(&Utc).trunc_subsecs(0));
+    }
+
     // Формат даты: "DD-MM-YYYY HH:MM"
-    let parsed_date = NaiveDateTime::parse_from_str(date_str, "%d-%m-%Y %H:%M")
-        .map_err(|e| format!("Error parsing date: {e}"))?;
+    let local = match NaiveDateTime::parse_from_str(date_str.trim(), FORMAT) {
+        Ok(parsed_date) => parsed_date,
+        Err(_) => parse_relative(date_str, zone.localize(now).naive_local())
+            .map_err(|e| format!("Error parsing date: {e}"))?,
+    };
+
+    zone.resolve(local)
+        .map(|date| date.trunc_subsecs(0))
+        .map_err(|e| format!("Error parsing date: {e}"))
+}
+
+/// The moment `age` (e.g. `30d`, `2 weeks` or `6mo`) before `now`.
+///
+/// # Errors
+///
+/// Returns a message if `age` is not a number followed by a unit.
+pub fn ago(age: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let tokens = tokenize(age);
+    let (amount, unit, consumed) = parse_duration(&tokens)?;
+    if consumed != tokens.len() {
+        return Err(format!("unexpected `{}` after the duration", tokens[consumed..].join(" ")));
+    }
+    apply_offset(now.naive_utc(), amount, &unit, true).map(|date| date.and_utc())
+}
+
+fn tokenize(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for word in input.split_whitespace() {
+        // Absolute dates and times keep their dashes; everywhere else `+` and
+        // `-` are offset signs.
+        if word.chars().all(|c| c.is_ascii_digit() || c == '-' || c == ':') {
+            tokens.push(word.to_string());
+            continue;
+        }
+        let mut current = String::new();
+        for c in word.chars() {
+            if c == '+' || c == '-' {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                tokens.push(c.to_string());
+            } else {
+                current.push(c.to_ascii_lowercase());
+            }
+        }
+        if !current.is_empty() {
+            tokens.push(current);
+        }
+    }
+    tokens
+}
+
+/// How a base date that turned out to be in the past is moved forward.
+#[derive(Debug, Clone, Copy)]
+enum Roll {
+    Never,
+    Day,
+    Week,
+    DayOfMonth(u32),
+    DayOfYear(u32, u32),
+}
+
+fn parse_relative(input: &str, now: NaiveDateTime) -> Result<NaiveDateTime, String> {
+    let tokens = tokenize(input);
+    let mut rest: &[String] = &tokens;
+
+    if rest.first().is_some_and(|t| t == "in") {
+        rest = &rest[1..];
+        if rest.is_empty() {
+            return Err("expected a duration after `in`".to_string());
+        }
+        let mut date = now;
+        while !rest.is_empty() {
+            let (amount, unit, consumed) = parse_duration(rest)?;
+            date = apply_offset(date, amount, &unit, false)?;
+            rest = &rest[consumed..];
+            if rest.first().is_some_and(|t| t == "and") && rest.len() > 1 {
+                rest = &rest[1..];
+            }
+        }
+        return Ok(date);
+    }
+
+    let mut date;
+    if let Some((time, consumed)) = parse_time(rest)? {
+        date = roll_forward(now.date().and_time(time), now, Roll::Day)?;
+        rest = &rest[consumed..];
+    } else {
+        let (base, consumed, roll) = parse_base(rest, now)?;
+        rest = &rest[consumed..];
+
+        let explicit_at = rest.first().is_some_and(|t| t == "at");
+        if explicit_at {
+            rest = &rest[1..];
+        }
+        date = base;
+        match parse_time(rest)? {
+            Some((time, consumed)) => {
+                date = date.date().and_time(time);
+                rest = &rest[consumed..];
+            }
+            None if explicit_at => return Err("expected a time after `at`".to_string()),
+            None => {}
+        }
+        date = roll_forward(date, now, roll)?;
+    }
+
+    while let Some(sign) = rest.first() {
+        let negative = match sign.as_str() {
+            "+" => false,
+            "-" => true,
+            other => return Err(format!("unexpected `{other}`")),
+        };
+        let (amount, unit, consumed) = parse_duration(&rest[1..])?;
+        date = apply_offset(date, amount, &unit, negative)?;
+        rest = &rest[1 + consumed..];
+    }
+
+    Ok(date)
+}
+
+fn parse_base(tokens: &[String], now: NaiveDateTime) -> Result<(NaiveDateTime, usize, Roll), String> {
+    let today = now.date();
+    let words: Vec<&str> = tokens.iter().map(String::as_str).collect();
+
+    if let [date, time, ..] = words.as_slice() {
+        if let Ok(parsed) = NaiveDateTime::parse_from_str(&format!("{date} {time}"), FORMAT) {
+            return Ok((parsed, 2, Roll::Never));
+        }
+    }
+
+    let words = match words.as_slice() {
+        ["the", rest @ ..] => rest,
+        words => words,
+    };
+    let article = usize::from(words.len() < tokens.len());
+
+    let (date, consumed, roll) = match words {
+        ["now", ..] => (now, 1, Roll::Never),
+        ["today", ..] => (midnight(today), 1, Roll::Never),
+        ["tomorrow", ..] => (midnight(today + Days::new(1)), 1, Roll::Never),
+        ["yesterday", ..] => (midnight(today - Days::new(1)), 1, Roll::Never),
+        [abbreviation @ ("eod" | "eow" | "eom"), ..] => {
+            let period = match *abbreviation {
+                "eod" => "day",
+                "eow" => "week",
+                _ => "month",
+            };
+            (end_of_day(period_bounds(today, period)?.1), 1, Roll::Never)
+        }
+        [direction @ ("next" | "last"), period @ ("week" | "month"), ..] => {
+            let (first, _) = period_bounds(today, period)?;
+            let first = match (*direction, *period) {
+                ("next", "week") => first + Days::new(7),
+                ("last", "week") => first - Days::new(7),
+                ("next", _) => first + Months::new(1),
+                _ => first - Months::new(1),
+            };
+            (midnight(first), 2, Roll::Never)
+        }
+        [direction @ ("next" | "last"), weekday, ..] => {
+            let weekday = parse_weekday(weekday).ok_or_else(|| format!("unknown weekday `{weekday}`"))?;
+            let date = if *direction == "next" {
+                next_weekday(today, weekday)
+            } else {
+                last_weekday(today, weekday)
+            };
+            (midnight(date), 2, Roll::Never)
+        }
+        [boundary @ ("start" | "end"), "of", period, ..] => {
+            let (first, last) = period_bounds(today, period)?;
+            let date = if *boundary == "start" {
+                midnight(first)
+            } else {
+                end_of_day(last)
+            };
+            (date, 3, Roll::Never)
+        }
+        [word, ..] if parse_weekday(word).is_some() => {
+            let weekday = parse_weekday(word).ok_or("invalid weekday")?;
+            let date = if today.weekday() == weekday {
+                today
+            } else {
+                next_weekday(today, weekday)
+            };
+            (midnight(date), 1, Roll::Week)
+        }
+        [word, rest @ ..] if parse_ordinal(word).is_some() => {
+            let day = parse_ordinal(word).ok_or("invalid ordinal")?;
+            let this_month = today.with_day(1).ok_or("invalid date")?;
+            match rest {
+                ["of", "this", "month", ..] => {
+                    let date = this_month.with_day(day).ok_or_else(|| format!("this month has no {word}"))?;
+                    (midnight(date), 4, Roll::Never)
+                }
+                ["of", "next", "month", ..] => {
+                    let date = (this_month + Months::new(1))
+                        .with_day(day)
+                        .ok_or_else(|| format!("next month has no {word}"))?;
+                    (midnight(date), 4, Roll::Never)
+                }
+                ["of", month, ..] => {
+                    let month = parse_month(month).ok_or_else(|| format!("unknown month `{month}`"))?;
+                    let date = day_of_year_from(today.year(), month, day)
+                        .ok_or_else(|| format!("there is no {word} in that month"))?;
+                    (midnight(date), 3, Roll::DayOfYear(month, day))
+                }
+                _ => {
+                    let date = day_of_month_from(this_month, day)
+                        .ok_or_else(|| format!("no month has a {word}"))?;
+                    (midnight(date), 1, Roll::DayOfMonth(day))
+                }
+            }
+        }
+        [other, ..] => return Err(format!("unrecognised date expression `{other}`")),
+        [] => return Err("empty date".to_string()),
+    };
+
+    Ok((date, consumed + article, roll))
+}
+
+fn roll_forward(date: NaiveDateTime, now: NaiveDateTime, roll: Roll) -> Result<NaiveDateTime, String> {
+    if date > now {
+        return Ok(date);
+    }
+    let time = date.time();
+    let rolled = match roll {
+        Roll::Never => Some(date.date()),
+        Roll::Day => Some(date.date() + Days::new(1)),
+        Roll::Week => Some(date.date() + Days::new(7)),
+        Roll::DayOfMonth(day) => date
+            .date()
+            .with_day(1)
+            .and_then(|first| day_of_month_from(first + Months::new(1), day)),
+        Roll::DayOfYear(month, day) => day_of_year_from(date.year() + 1, month, day),
+    };
+    rolled
+        .map(|d| d.and_time(time))
+        .ok_or_else(|| "date out of range".to_string())
+}
+
+/// Parses a time of day at the start of `tokens`, returning it with the
+/// number of tokens consumed.
+fn parse_time(tokens: &[String]) -> Result<Option<(NaiveTime, usize)>, String> {
+    let Some(first) = tokens.first() else {
+        return Ok(None);
+    };
+
+    match first.as_str() {
+        "noon" => return Ok(Some((NaiveTime::from_hms_opt(12, 0, 0).ok_or("invalid time")?, 1))),
+        "midnight" => return Ok(Some((NaiveTime::MIN, 1))),
+        _ => {}
+    }
+
+    let next = tokens.get(1).map(String::as_str);
+    let (clock, meridiem, consumed) = match (first.strip_suffix("am"), first.strip_suffix("pm"), next) {
+        (Some(clock), _, _) => (clock, Some(false), 1),
+        (_, Some(clock), _) => (clock, Some(true), 1),
+        (_, _, Some("am")) => (first.as_str(), Some(false), 2),
+        (_, _, Some("pm")) => (first.as_str(), Some(true), 2),
+        _ => (first.as_str(), None, 1),
+    };
+
+    if clock.is_empty() || !clock.chars().all(|c| c.is_ascii_digit() || c == ':') {
+        return Ok(None);
+    }
+
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) => (hour, minute),
+        None if meridiem.is_some() => (clock, "0"),
+        None => return Err(format!("ambiguous time `{clock}`, use {clock}am, {clock}pm or HH:MM")),
+    };
+    let invalid = || format!("invalid time `{first}`");
+    let mut hour: u32 = hour.parse().map_err(|_| invalid())?;
+    let minute: u32 = minute.parse().map_err(|_| invalid())?;
+
+    if let Some(pm) = meridiem {
+        if !(1..=12).contains(&hour) {
+            return Err(invalid());
+        }
+        hour = match (hour, pm) {
+            (12, false) => 0,
+            (12, true) => 12,
+            (hour, true) => hour + 12,
+            (hour, false) => hour,
+        };
+    }
+
+    NaiveTime::from_hms_opt(hour, minute, 0)
+        .map(|time| Some((time, consumed)))
+        .ok_or_else(invalid)
+}
+
+fn parse_duration(tokens: &[String]) -> Result<(u32, String, usize), String> {
+    let first = tokens.first().ok_or("expected a duration after sign")?;
+    let digits: String = first.chars().take_while(char::is_ascii_digit).collect();
+    if digits.is_empty() {
+        return Err(format!("expected a duration, found `{first}`"));
+    }
+    let amount = digits.parse::<u32>().map_err(|e| e.to_string())?;
+    let unit = &first[digits.len()..];
+
+    if unit.is_empty() {
+        let unit = tokens.get(1).ok_or_else(|| format!("missing unit after `{amount}`"))?;
+        Ok((amount, unit.clone(), 2))
+    } else {
+        Ok((amount, unit.to_string(), 1))
+    }
+}
+
+fn apply_offset(date: NaiveDateTime, amount: u32, unit: &str, negative: bool) -> Result<NaiveDateTime, String> {
+    let amount_i64 = i64::from(amount);
+    let duration = match unit {
+        "m" | "min" | "mins" | "minute" | "minutes" => Duration::minutes(amount_i64),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Duration::hours(amount_i64),
+        "d" | "day" | "days" => Duration::days(amount_i64),
+        "w" | "week" | "weeks" => Duration::weeks(amount_i64),
+        "mo" | "month" | "months" => {
+            let months = Months::new(amount);
+            let shifted = if negative {
+                date.checked_sub_months(months)
+            } else {
+                date.checked_add_months(months)
+            };
+            return shifted.ok_or_else(|| "date out of range".to_string());
+        }
+        other => return Err(format!("unknown duration unit `{other}`")),
+    };
+
+    let shifted = if negative {
+        date.checked_sub_signed(duration)
+    } else {
+        date.checked_add_signed(duration)
+    };
+    shifted.ok_or_else(|| "date out of range".to_string())
+}
+
+fn parse_weekday(word: &str) -> Option<Weekday> {
+    match word {
+        "mon" | "monday" => Some(Weekday::Mon),
+        "tue" | "tues" | "tuesday" => Some(Weekday::Tue),
+        "wed" | "wednesday" => Some(Weekday::Wed),
+        "thu" | "thur" | "thurs" | "thursday" => Some(Weekday::Thu),
+        "fri" | "friday" => Some(Weekday::Fri),
+        "sat" | "saturday" => Some(Weekday::Sat),
+        "sun" | "sunday" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+fn parse_month(word: &str) -> Option<u32> {
+    let month = match word {
+        "jan" | "january" => 1,
+        "feb" | "february" => 2,
+        "mar" | "march" => 3,
+        "apr" | "april" => 4,
+        "may" => 5,
+        "jun" | "june" => 6,
+        "jul" | "july" => 7,
+        "aug" | "august" => 8,
+        "sep" | "sept" | "september" => 9,
+        "oct" | "october" => 10,
+        "nov" | "november" => 11,
+        "dec" | "december" => 12,
+        _ => return None,
+    };
+    Some(month)
+}
+
+/// Parses `1st`, `2nd`, `3rd`, `4th`, ... `31st`.
+fn parse_ordinal(word: &str) -> Option<u32> {
+    let digits = word.strip_suffix("st")
+        .or_else(|| word.strip_suffix("nd"))
+        .or_else(|| word.strip_suffix("rd"))
+        .or_else(|| word.strip_suffix("th"))?;
+    digits.parse().ok().filter(|day| (1..=31).contains(day))
+}
+
+/// The first month starting at `first` (the 1st of a month) that has `day`.
+fn day_of_month_from(first: NaiveDate, day: u32) -> Option<NaiveDate> {
+    (0..12).find_map(|k| (first + Months::new(k)).with_day(day))
+}
+
+/// The first year starting at `year` that has `month`/`day` (29 February
+/// only exists in leap years).
+fn day_of_year_from(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+    (0..8).find_map(|k| NaiveDate::from_ymd_opt(year + k, month, day))
+}
+
+/// The first `weekday` strictly after `from`.
+fn next_weekday(from: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let ahead = (7 + weekday.num_days_from_monday() - from.weekday().num_days_from_monday()) % 7;
+    from + Days::new(u64::from(if ahead == 0 { 7 } else { ahead }))
+}
+
+/// The last `weekday` strictly before `from`.
+fn last_weekday(from: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let behind = (7 + from.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+    from - Days::new(u64::from(if behind == 0 { 7 } else { behind }))
+}
+
+/// First and last day of the day, (Monday-based) week or month containing `date`.
+fn period_bounds(date: NaiveDate, period: &str) -> Result<(NaiveDate, NaiveDate), String> {
+    match period {
+        "day" => Ok((date, date)),
+        "week" => {
+            let monday = date - Days::new(u64::from(date.weekday().num_days_from_monday()));
+            Ok((monday, monday + Days::new(6)))
+        }
+        "month" => {
+            let first = date.with_day(1).ok_or("invalid date")?;
+            let last = (first + Months::new(1)) - Days::new(1);
+            Ok((first, last))
+        }
+        other => Err(format!("unknown period `{other}`, expected day, week or month")),
+    }
+}
+
+const fn midnight(date: NaiveDate) -> NaiveDateTime {
+    date.and_time(NaiveTime::MIN)
+}
+
+fn end_of_day(date: NaiveDate) -> NaiveDateTime {
+    date.and_hms_opt(23, 59, 0).unwrap_or_else(|| midnight(date))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc() -> Zone {
+        Zone::parse("UTC").expect("valid zone")
+    }
+
+    fn parse_at(input: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+        parse_in(input, now, utc())
+    }
+
+    fn at(input: &str) -> DateTime<Utc> {
+        // Wednesday
+        let now = parse_at("14-06-2023 15:30", Utc::now()).expect("valid date");
+        parse_at(input, now).expect("expression should parse")
+    }
+
+    fn expected(input: &str) -> DateTime<Utc> {
+        parse_at(input, Utc::now()).expect("valid date")
+    }
+
+    #[test]
+    fn test_parse_absolute_date() {
+        assert_eq!(at("'01-01-2021 12:00'"), expected("01-01-2021 12:00"));
+    }
+
+    #[test]
+    fn test_parse_relative_days() {
+        assert_eq!(at("now"), expected("14-06-2023 15:30"));
+        assert_eq!(at("today"), expected("14-06-2023 00:00"));
+        assert_eq!(at("tomorrow 09:00"), expected("15-06-2023 09:00"));
+        assert_eq!(at("yesterday"), expected("13-06-2023 00:00"));
+    }
+
+    #[test]
+    fn test_parse_offsets() {
+        assert_eq!(at("now + 3d"), expected("17-06-2023 15:30"));
+        assert_eq!(at("now+3d"), expected("17-06-2023 15:30"));
+        assert_eq!(at("today - 2 hours"), expected("13-06-2023 22:00"));
+        assert_eq!(at("now + 1w + 30m"), expected("21-06-2023 16:00"));
+        assert_eq!(at("01-01-2023 10:00 + 1mo"), expected("01-02-2023 10:00"));
+    }
+
+    #[test]
+    fn test_ago() {
+        let now = expected("14-06-2023 15:30");
+        assert_eq!(ago("30d", now), Ok(expected("15-05-2023 15:30")));
+        assert_eq!(ago("2 weeks", now), Ok(expected("31-05-2023 15:30")));
+        assert_eq!(ago("1mo", now), Ok(expected("14-05-2023 15:30")));
+        assert!(ago("30", now).is_err());
+        assert!(ago("30d ago", now).is_err());
+    }
+
+    #[test]
+    fn test_parse_weekdays_and_periods() {
+        assert_eq!(at("next monday"), expected("19-06-2023 00:00"));
+        assert_eq!(at("next wed"), expected("21-06-2023 00:00"));
+        assert_eq!(at("last friday 18:00"), expected("09-06-2023 18:00"));
+        assert_eq!(at("end of week"), expected("18-06-2023 23:59"));
+        assert_eq!(at("start of week"), expected("12-06-2023 00:00"));
+        assert_eq!(at("end of month"), expected("30-06-2023 23:59"));
+    }
+
+    #[test]
+    fn test_parse_natural_language() {
+        assert_eq!(at("in 2 hours"), expected("14-06-2023 17:30"));
+        assert_eq!(at("in 1 week and 2 days"), expected("23-06-2023 15:30"));
+        assert_eq!(at("fri 5pm"), expected("16-06-2023 17:00"));
+        assert_eq!(at("Friday at 5:30 PM"), expected("16-06-2023 17:30"));
+        assert_eq!(at("tomorrow at noon"), expected("15-06-2023 12:00"));
+        assert_eq!(at("3rd of next month"), expected("03-07-2023 00:00"));
+        assert_eq!(at("the 20th"), expected("20-06-2023 00:00"));
+        assert_eq!(at("eow"), expected("18-06-2023 23:59"));
+        assert_eq!(at("eod"), expected("14-06-2023 23:59"));
+        assert_eq!(at("next week"), expected("19-06-2023 00:00"));
+        assert_eq!(at("next month"), expected("01-07-2023 00:00"));
+    }
+
+    #[test]
+    fn test_parse_never_resolves_bare_expressions_to_the_past() {
+        // Today is Wednesday 14 June, 15:30.
+        assert_eq!(at("wed"), expected("21-06-2023 00:00"));
+        assert_eq!(at("wed 6pm"), expected("14-06-2023 18:00"));
+        assert_eq!(at("wed 9am"), expected("21-06-2023 09:00"));
+        assert_eq!(at("9am"), expected("15-06-2023 09:00"));
+        assert_eq!(at("18:00"), expected("14-06-2023 18:00"));
+        assert_eq!(at("10th"), expected("10-07-2023 00:00"));
+        assert_eq!(at("31st"), expected("31-07-2023 00:00"));
+        assert_eq!(at("3rd of march"), expected("03-03-2024 00:00"));
+        assert_eq!(at("29th of feb"), expected("29-02-2024 00:00"));
+    }
+
+    #[test]
+    fn test_parse_rejects_ambiguous_times() {
+        let now = Utc::now();
+        assert!(parse_at("fri at 5", now).is_err());
+        assert!(parse_at("tomorrow at", now).is_err());
+        assert!(parse_at("13pm", now).is_err());
+        assert!(parse_at("31st of next month", parse_at("14-08-2023 10:00", now).expect("valid date")).is_err());
+    }
+
+    #[test]
+    fn test_parse_in_zone() {
+        let zone = Zone::parse("Asia/Tokyo").expect("valid zone");
+        let now = expected("14-06-2023 20:00");
+        // 20:00 UTC is already 05:00 on the 15th in Tokyo.
+        assert_eq!(parse_in("today", now, zone), Ok(expected("14-06-2023 15:00")));
+        assert_eq!(parse_in("15-06-2023 09:00", now, zone), Ok(expected("15-06-2023 00:00")));
+        assert_eq!(parse_in("2023-06-15T09:00:00+02:00", now, zone), Ok(expected("15-06-2023 07:00")));
+    }
 
-    Ok(DateTime::<Utc>::from_naive_utc_and_offset(parsed_date, Utc))
+    #[test]
+    fn test_parse_rejects_garbage() {
+        let now = Utc::now();
+        assert!(parse_at("someday", now).is_err());
+        assert!(parse_at("now + 3 parsecs", now).is_err());
+        assert!(parse_at("", now).is_err());
+    }
 }
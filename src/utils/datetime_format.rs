@@ -1,8 +1,14 @@
-use chrono::{DateTime, Utc, NaiveDateTime, TimeZone};
+use chrono::{DateTime, Utc, NaiveDateTime, SecondsFormat};
 use serde::{self, Deserialize, Serializer, Deserializer};
 
-const FORMAT: &str = "%d-%m-%Y %H:%M";
+/// Format written by versions that stored naive UTC dates without an offset.
+const LEGACY_FORMAT: &str = "%d-%m-%Y %H:%M";
 
+/// Writes `date` as RFC 3339 in UTC.
+///
+/// # Errors
+///
+/// Returns whatever error `serializer` reports.
 pub fn serialize<S>(
     date: &DateTime<Utc>,
     serializer: S,
@@ -10,10 +16,15 @@ pub fn serialize<S>(
 where
     S: Serializer,
 {
-    let s = format!("{}", date.format(FORMAT));
-    serializer.serialize_str(&s)
+    serializer.serialize_str(&format(*date))
 }
 
+/// Reads a date written by [`serialize`] or by an older version (see
+/// [`parse`]).
+///
+/// # Errors
+///
+/// Fails if the value is not a string in either format.
 pub fn deserialize<'de, D>(
     deserializer: D,
 ) -> Result<DateTime<Utc>, D::Error>
@@ -21,9 +32,53 @@ where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
-    NaiveDateTime::parse_from_str(&s, FORMAT)
-        .map_err(serde::de::Error::custom)
-        .and_then(|ndt| Utc.from_local_datetime(&ndt)
-            .single()
-            .ok_or_else(|| serde::de::Error::custom("Ambiguous local time")))
+    parse(&s).ok_or_else(|| serde::de::Error::custom(format!("invalid date `{s}`")))
+}
+
+/// The same for an optional date, for fields that also use
+/// `#[serde(default, skip_serializing_if = "Option::is_none")]`.
+pub mod optional {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// # Errors
+    ///
+    /// Returns whatever error `serializer` reports.
+    pub fn serialize<S>(date: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match date {
+            Some(date) => super::serialize(date, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Fails if the value is neither null nor a date.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| super::parse(&s).ok_or_else(|| serde::de::Error::custom(format!("invalid date `{s}`"))))
+            .transpose()
+    }
+}
+
+/// The stored form of a date: RFC 3339 in UTC, to the second.
+#[must_use]
+pub fn format(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Parses an RFC 3339 timestamp, or a legacy `DD-MM-YYYY HH:MM` value which
+/// is taken to be UTC (that is how it was written).
+#[must_use]
+pub fn parse(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|date| date.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(s, LEGACY_FORMAT).map(|ndt| ndt.and_utc()))
+        .ok()
 }
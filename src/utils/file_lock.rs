@@ -0,0 +1,69 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// An exclusive advisory lock on `<path>.lock`, held until dropped.
+///
+/// The lock lives in a separate file because the data file itself is
+/// replaced on every save. The lock file is never removed, as deleting it
+/// would let two processes lock different files.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Locks `path`, waiting up to `timeout` for other processes to release
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind [`io::ErrorKind::TimedOut`] if the lock is
+    /// still held when `timeout` runs out, or the error from creating the
+    /// lock file.
+    pub fn acquire(path: &Path, timeout: Duration) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(lock_path(path))?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(Self { _file: file }),
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => thread::sleep(RETRY_INTERVAL),
+                Err(TryLockError::WouldBlock) => return Err(io::ErrorKind::TimedOut.into()),
+                Err(TryLockError::Error(e)) => return Err(e),
+            }
+        }
+    }
+}
+
+#[must_use]
+pub fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_lock_times_out_until_first_is_dropped() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("db.json");
+
+        let held = FileLock::acquire(&path, Duration::ZERO).expect("first lock");
+        let waiting = FileLock::acquire(&path, Duration::from_millis(100));
+        assert_eq!(waiting.map(|_| ()).map_err(|e| e.kind()), Err(io::ErrorKind::TimedOut));
+
+        drop(held);
+        assert!(FileLock::acquire(&path, Duration::ZERO).is_ok());
+    }
+}
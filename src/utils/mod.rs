@@ -1,3 +1,5 @@
+pub mod atomic_file;
 pub mod datetime_format;
 pub mod date;
-
+pub mod file_lock;
+pub mod timezone;
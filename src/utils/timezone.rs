@@ -0,0 +1,121 @@
+use chrono::{DateTime, FixedOffset, Local, LocalResult, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::env;
+
+/// The zone dates are entered and displayed in. It is taken from `TODO_TZ`
+/// (an IANA name such as `Europe/Berlin`, `UTC`, or a fixed offset such as
+/// `+02:00`) and defaults to the system time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    Local,
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+impl Zone {
+    /// The zone named by `TODO_TZ`, falling back to the system time zone
+    /// when it is unset or invalid (use [`Zone::from_env`] to detect that).
+    #[must_use]
+    pub fn current() -> Self {
+        Self::from_env().unwrap_or(Self::Local)
+    }
+
+    /// Reads `TODO_TZ`; an unset or blank variable means the system time zone.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message naming the zone if it is not recognised.
+    pub fn from_env() -> Result<Self, String> {
+        match env::var("TODO_TZ") {
+            Ok(name) if !name.trim().is_empty() => Self::parse(&name),
+            _ => Ok(Self::Local),
+        }
+    }
+
+    /// Parses an IANA name, `local`, or a fixed offset such as `+02:00`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message naming the zone if it is not recognised.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("local") {
+            return Ok(Self::Local);
+        }
+        if let Ok(offset) = name.parse::<FixedOffset>() {
+            return Ok(Self::Fixed(offset));
+        }
+        name.parse::<Tz>()
+            .map(Self::Named)
+            .map_err(|_| format!("Unknown time zone `{name}`"))
+    }
+
+    /// Converts a stored instant into this zone for display.
+    #[must_use]
+    pub fn localize(self, date: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            Self::Local => date.with_timezone(&Local).fixed_offset(),
+            Self::Named(tz) => date.with_timezone(&tz).fixed_offset(),
+            Self::Fixed(offset) => date.with_timezone(&offset),
+        }
+    }
+
+    /// Interprets a wall-clock time in this zone. Times repeated by a DST
+    /// change resolve to the earlier instant.
+    ///
+    /// # Errors
+    ///
+    /// Fails for times skipped by a DST change.
+    pub fn resolve(self, naive: NaiveDateTime) -> Result<DateTime<Utc>, String> {
+        match self {
+            Self::Local => resolve_in(&Local, naive),
+            Self::Named(tz) => resolve_in(&tz, naive),
+            Self::Fixed(offset) => resolve_in(&offset, naive),
+        }
+    }
+}
+
+fn resolve_in<T: TimeZone>(zone: &T, naive: NaiveDateTime) -> Result<DateTime<Utc>, String> {
+    match zone.from_local_datetime(&naive) {
+        LocalResult::Single(date) | LocalResult::Ambiguous(date, _) => Ok(date.with_timezone(&Utc)),
+        LocalResult::None => Err(format!("{naive} does not exist in the selected time zone")),
+    }
+}
+
+/// Formats `date` as RFC 3339 in `zone`, using `Z` for a zero offset.
+#[must_use]
+pub fn format(date: DateTime<Utc>, zone: Zone) -> String {
+    zone.localize(date).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive(input: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(input, "%d-%m-%Y %H:%M").expect("valid date")
+    }
+
+    #[test]
+    fn test_parse_zone_names() {
+        assert_eq!(Zone::parse("Europe/Berlin"), Ok(Zone::Named(chrono_tz::Europe::Berlin)));
+        assert_eq!(Zone::parse("local"), Ok(Zone::Local));
+        assert!(matches!(Zone::parse("+05:30"), Ok(Zone::Fixed(_))));
+        assert!(Zone::parse("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn test_round_trip_through_named_zone() {
+        let zone = Zone::Named(chrono_tz::America::New_York);
+        let stored = zone.resolve(naive("14-06-2023 09:00")).expect("valid local time");
+        assert_eq!(format(stored, Zone::parse("UTC").expect("valid zone")), "2023-06-14T13:00:00Z");
+        assert_eq!(format(stored, zone), "2023-06-14T09:00:00-04:00");
+    }
+
+    #[test]
+    fn test_dst_gap_is_rejected() {
+        let zone = Zone::Named(chrono_tz::Europe::Berlin);
+        assert!(zone.resolve(naive("26-03-2023 02:30")).is_err());
+        assert!(zone.resolve(naive("29-10-2023 02:30")).is_ok());
+    }
+}
@@ -10,6 +10,7 @@ fn create_todo_list_command() -> (Command, tempfile::TempDir) {
     let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
 
     cmd.env("APP_ENV", "test")
+       .env("TODO_TZ", "UTC")
        .env("TODO_FILE", temp_dir.path().join(TEST_JSON_FILE));
     (cmd, temp_dir)
 }
@@ -65,7 +66,8 @@ fn test_add_and_select_all_tasks() {
 
     let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
 
-    cmd.arg("add")
+    cmd.env("TODO_FILE", temp_dir.path().join(TEST_JSON_FILE))
+        .arg("add")
         .arg("Task B1")
         .arg("Description B1")
         .arg("1-1-2021 12:00")
@@ -82,7 +84,8 @@ fn test_add_and_select_all_tasks() {
         .success()
         .stdout(predicate::str::contains("Task A")
             .and(predicate::str::contains("Description A"))
-            .and(predicate::str::contains("Category A")));
+            .and(predicate::str::contains("Category A"))
+            .and(predicate::str::contains("Task B1")));
 }
 
 #[test]
@@ -110,7 +113,7 @@ fn test_add_and_done_task() {
         .arg("select")
         .assert()
         .success()
-        .stdout(predicate::str::contains("status: true"));
+        .stdout(predicate::str::contains("status: done"));
 }
 
 #[test]
@@ -131,7 +134,7 @@ fn test_add_and_delete_task() {
         .arg("Test Task")
         .assert()
         .success()
-        .stdout(predicate::str::contains("Task deleted successfully!"));
+        .stdout(predicate::str::contains("Task moved to the trash."));
 
     let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
     cmd.env("TODO_FILE", temp_dir.path().join("tasks.json"))
@@ -243,3 +246,737 @@ fn test_add_multiple_tasks_and_select() {
             .and(predicate::str::contains("Category A"))
             .and(predicate::str::contains("Task 2").not()));
 }
+
+#[test]
+fn test_add_and_select_with_relative_dates() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+
+    cmd.env("TODO_NOW", "14-06-2023 15:30")
+        .arg("add")
+        .arg("Relative Task")
+        .arg("Due tomorrow morning")
+        .arg("tomorrow 09:00")
+        .arg("Category")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Task added successfully!"));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", temp_dir.path().join(TEST_JSON_FILE))
+        .env("TODO_NOW", "14-06-2023 15:30")
+        .arg("select")
+        .arg("* where date > today and date < end of week")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Relative Task")
+            .and(predicate::str::contains("2023-06-15T09:00:00Z")));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", temp_dir.path().join(TEST_JSON_FILE))
+        .env("TODO_NOW", "20-06-2023 08:00")
+        .arg("select")
+        .arg("* where date < today")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Relative Task"));
+}
+
+#[test]
+fn test_update_task_with_natural_language_date() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+
+    cmd.env("TODO_NOW", "14-06-2023 15:30")
+        .arg("add")
+        .arg("Report")
+        .arg("Quarterly report")
+        .arg("fri 5pm")
+        .arg("Work")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", temp_dir.path().join(TEST_JSON_FILE))
+        .env("TODO_NOW", "14-06-2023 15:30")
+        .arg("update")
+        .arg("Report")
+        .write_stdin("\n\nin 2 hours\n\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Task updated successfully!"));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", temp_dir.path().join(TEST_JSON_FILE))
+        .arg("select")
+        .arg("*")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2023-06-14T17:30:00Z")
+            .and(predicate::str::contains("Quarterly report")));
+}
+
+#[test]
+fn test_dates_are_stored_with_offsets_and_rendered_in_viewer_zone() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+
+    cmd.env("TODO_TZ", "Europe/Berlin")
+        .arg("add")
+        .arg("Sync")
+        .arg("Cross-team sync")
+        .arg("14-06-2023 09:00")
+        .arg("Work")
+        .assert()
+        .success();
+
+    let contents = std::fs::read_to_string(&db_path).expect("Failed to read the database");
+    assert!(contents.contains("2023-06-14T07:00:00Z"));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .env("TODO_TZ", "America/New_York")
+        .arg("select")
+        .arg("* where date = 14-06-2023 03:00")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2023-06-14T03:00:00-04:00"));
+}
+
+#[test]
+fn test_legacy_dates_are_migrated_on_load() {
+    let temp_dir = tempdir().expect("Failed to create a temporary directory");
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+    std::fs::write(
+        &db_path,
+        r#"{"tasks":{"Old":{"name":"Old","description":"Legacy","date":"01-01-2021 12:00","category":"Misc","status":false}}}"#,
+    )
+    .expect("Failed to write the database");
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .env("TODO_TZ", "UTC")
+        .arg("select")
+        .arg("*")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2021-01-01T12:00:00Z"));
+
+    let contents = std::fs::read_to_string(&db_path).expect("Failed to read the database");
+    assert!(contents.contains("\"date\": \"2021-01-01T12:00:00Z\""));
+}
+
+#[test]
+fn test_migrate_dry_run_leaves_the_file_alone() {
+    let temp_dir = tempdir().expect("Failed to create a temporary directory");
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+    let legacy = r#"{"tasks":{"Old":{"name":"Old","description":"Legacy","date":"01-01-2021 12:00","category":"Misc","status":false}}}"#;
+    std::fs::write(&db_path, legacy).expect("Failed to write the database");
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .args(["migrate", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("schema version 0"))
+        .stdout(predicate::str::contains("0 -> 1"))
+        .stdout(predicate::str::contains("1 -> 2"));
+    assert_eq!(std::fs::read_to_string(&db_path).expect("Failed to read the database"), legacy);
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path).arg("migrate").assert().success().stdout(predicate::str::contains(".v0.bak"));
+    let contents = std::fs::read_to_string(&db_path).expect("Failed to read the database");
+    assert!(contents.contains("\"version\": 5"));
+    assert!(temp_dir.path().join(format!("{TEST_JSON_FILE}.v0.bak")).exists());
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .args(["migrate", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("up to date"));
+}
+
+#[test]
+fn test_unsupported_schema_version_is_refused() {
+    let temp_dir = tempdir().expect("Failed to create a temporary directory");
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+    let future = r#"{"version":99,"tasks":{},"next_id":0}"#;
+    std::fs::write(&db_path, future).expect("Failed to write the database");
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .args(["select", "*"])
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains("schema version 99"));
+    assert_eq!(std::fs::read_to_string(&db_path).expect("Failed to read the database"), future);
+}
+
+#[test]
+fn test_duplicate_names_and_id_references() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+
+    cmd.args(["add", "Dup", "First", "1-1-2021 12:00", "Cat"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(id 1)"));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .args(["add", "Dup", "Second", "1-1-2021 12:00", "Cat"])
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains("already exists"));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .args(["add", "Dup", "Second", "1-1-2021 12:00", "Cat", "--force"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(id 2)"));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .args(["done", "Dup"])
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("ambiguous"));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .args(["done", "2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Task marked as done!"));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .args(["select", "status = done"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Second").and(predicate::str::contains("First").not()));
+}
+
+#[test]
+fn test_update_task_with_flags() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+
+    cmd.args(["add", "Flagged", "Keep this description", "1-1-2021 12:00", "Old Category"])
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .env("TODO_TZ", "UTC")
+        .args(["update", "Flagged", "--category", "New Category", "--date", "2-1-2021 08:30", "--status", "done"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Task updated successfully!")
+            .and(predicate::str::contains("New name").not()));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .env("TODO_TZ", "UTC")
+        .args(["select", "*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Keep this description")
+            .and(predicate::str::contains("New Category"))
+            .and(predicate::str::contains("2021-01-02T08:30:00Z"))
+            .and(predicate::str::contains("status: done")));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .args(["update", "Flagged"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No changes made."));
+}
+
+#[test]
+fn test_failures_exit_with_documented_codes() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+
+    cmd.args(["done", "Missing"])
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("Task not found"));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .args(["add", "Task", "Description", "someday", "Cat"])
+        .assert()
+        .code(5)
+        .stderr(predicate::str::contains("Invalid date"));
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .args(["select", "* where nonsense = 1"])
+        .assert()
+        .code(7)
+        .stderr(predicate::str::contains("Error parsing query"));
+}
+
+#[test]
+fn test_corrupt_file_is_kept_and_repaired() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+    let damaged = r#"{"tasks":{"1":{"id":1,"name":"Kept","description":"","date":"2021-01-01T12:00:00Z","category":"","status":false},"2":{"id":2,"name":"Lost","desc"#;
+    std::fs::write(&db_path, damaged).expect("Failed to write the database");
+
+    cmd.args(["select", "*"])
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains("todolist repair"));
+    assert_eq!(std::fs::read_to_string(&db_path).expect("Failed to read the database"), damaged);
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .arg("repair")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Recovered 1 task(s)"));
+
+    let copies = std::fs::read_dir(temp_dir.path())
+        .expect("Failed to list the directory")
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".corrupt"))
+        .count();
+    assert!(copies >= 1);
+
+    let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+    cmd.env("TODO_FILE", &db_path)
+        .env("TODO_TZ", "UTC")
+        .args(["select", "*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Kept").and(predicate::str::contains("Lost").not()));
+}
+
+#[test]
+fn test_parallel_adds_are_not_lost() {
+    let temp_dir = tempdir().expect("Failed to create a temporary directory");
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+
+    let workers: Vec<_> = (0..8)
+        .map(|i| {
+            let db_path = db_path.clone();
+            std::thread::spawn(move || {
+                Command::cargo_bin("todolist")
+                    .expect("Failed to find the 'todolist' binary")
+                    .env("TODO_FILE", &db_path)
+                    .args(["add", &format!("Task {i}"), "Parallel", "1-1-2021 12:00", "Cat"])
+                    .assert()
+                    .success();
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().expect("worker panicked");
+    }
+
+    let contents = std::fs::read_to_string(&db_path).expect("Failed to read the database");
+    for i in 0..8 {
+        assert!(contents.contains(&format!("\"Task {i}\"")), "Task {i} was lost");
+    }
+}
+
+#[test]
+fn test_locked_file_times_out() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+    let _held = todolist::TodoListService::open(&db_path).expect("Failed to open the database");
+
+    cmd.env("TODO_LOCK_TIMEOUT", "0.2")
+        .args(["select", "*"])
+        .assert()
+        .code(8)
+        .stderr(predicate::str::contains("locked by another todolist process"));
+}
+
+#[test]
+fn test_sqlite_store_imports_json_and_selects() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let json_path = temp_dir.path().join(TEST_JSON_FILE);
+    let sqlite_path = temp_dir.path().join("test_db.sqlite");
+
+    cmd.args(["add", "Imported", "From JSON", "1-1-2021 12:00", "work"])
+        .assert()
+        .success();
+
+    let sqlite = || {
+        let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+        cmd.env("TODO_STORE", "sqlite")
+            .env("TODO_FILE", &sqlite_path)
+            .env("TODO_TZ", "UTC");
+        cmd
+    };
+
+    sqlite().args(["import", json_path.to_str().expect("utf-8 path")])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Imported 1 task(s)"));
+    sqlite().args(["import", json_path.to_str().expect("utf-8 path")])
+        .assert()
+        .code(4);
+
+    sqlite().args(["add", "Native", "Added to SQLite", "2-1-2021 12:00", "home"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(id 2)"));
+    sqlite().args(["done", "Imported"])
+        .assert()
+        .success();
+
+    sqlite().args(["select", "category = work and status = done"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Imported").and(predicate::str::contains("Native").not()));
+}
+
+#[test]
+fn test_import_only_reads_its_source() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let source_dir = temp_dir.path().join("source");
+    std::fs::create_dir(&source_dir).expect("Failed to create the source directory");
+    let source = source_dir.join("old.json");
+    let legacy = r#"{"tasks":{"Old":{"name":"Old","description":"Legacy","date":"01-01-2021 12:00","category":"Misc","status":true}}}"#;
+    std::fs::write(&source, legacy).expect("Failed to write the source");
+
+    cmd.args(["import", source.to_str().expect("utf-8 path")])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Imported 1 task(s)"));
+
+    assert_eq!(std::fs::read_to_string(&source).expect("Failed to read the source"), legacy);
+    let files: Vec<_> = std::fs::read_dir(&source_dir).expect("Failed to list the source directory").collect();
+    assert_eq!(files.len(), 1);
+    let imported = std::fs::read_to_string(temp_dir.path().join(TEST_JSON_FILE)).expect("Failed to read the database");
+    assert!(imported.contains("\"status\": \"done\""));
+}
+
+#[test]
+fn test_event_log_store_answers_time_travel_queries() {
+    let temp_dir = tempdir().expect("Failed to create a temporary directory");
+    let log_path = temp_dir.path().join("test_db.jsonl");
+    let events = |now: &str| {
+        let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+        cmd.env("TODO_STORE", "events")
+            .env("TODO_FILE", &log_path)
+            .env("TODO_TZ", "UTC")
+            .env("TODO_NOW", now);
+        cmd
+    };
+
+    events("01-02-2024 09:00").args(["add", "Report", "Q1 numbers", "15-03-2024 12:00", "work"])
+        .assert()
+        .success();
+    events("02-02-2024 09:00").args(["add", "Scratch", "Throwaway", "15-03-2024 12:00", "misc"])
+        .assert()
+        .success();
+    events("10-03-2024 09:00").args(["done", "Report"]).assert().success();
+    events("11-03-2024 09:00").args(["delete", "Scratch"]).assert().success();
+
+    let log = std::fs::read_to_string(&log_path).expect("Failed to read the log");
+    assert_eq!(log.lines().count(), 4);
+    assert!(log.contains(r#""op":"done""#));
+
+    events("12-03-2024 09:00").args(["select", "status = pending"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No tasks match"));
+    events("12-03-2024 09:00").args(["select", "--as-of", "01-03-2024 00:00", "status = pending"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Report").and(predicate::str::contains("Scratch")));
+    events("12-03-2024 09:00").args(["select", "--as-of", "11-03-2024 12:00", "*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Report").and(predicate::str::contains("Scratch").not()));
+    events("12-03-2024 09:00").args(["select", "--as-of", "11-03-2024 12:00", "--include-trash", "*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Scratch").and(predicate::str::contains("deleted: 2024-03-11")));
+
+    let (mut cmd, _temp_dir) = create_todo_list_command();
+    cmd.args(["select", "--as-of", "01-03-2024 00:00", "*"])
+        .assert()
+        .code(9)
+        .stderr(predicate::str::contains("history"));
+}
+
+#[test]
+fn test_undo_and_redo_a_delete() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+    let todolist = || {
+        let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+        cmd.env("TODO_FILE", &db_path).env("TODO_TZ", "UTC");
+        cmd
+    };
+
+    cmd.args(["add", "Report", "Q1 numbers", "1-1-2021 12:00", "work"])
+        .assert()
+        .success();
+    todolist().args(["delete", "Report"]).assert().success();
+
+    todolist().args(["undo", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("undo delete of #1: restore #1 \"Report\"").and(predicate::str::contains("Dry run")));
+    todolist().args(["select", "*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No tasks match"));
+
+    todolist().arg("undo").assert().success().stdout(predicate::str::contains("Done."));
+    todolist().args(["select", "*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Q1 numbers"));
+
+    todolist().arg("redo")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("redo delete of #1: move #1 \"Report\" to the trash"));
+    todolist().arg("redo").assert().success().stdout(predicate::str::contains("Nothing to redo."));
+    todolist().args(["undo", "5"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("undo add of #1: remove #1"));
+    todolist().args(["select", "*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No tasks match"));
+}
+
+#[test]
+fn test_undo_and_redo_on_sqlite() {
+    let temp_dir = tempdir().expect("Failed to create a temporary directory");
+    let sqlite_path = temp_dir.path().join("test_db.sqlite");
+    let sqlite = || {
+        let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+        cmd.env("TODO_STORE", "sqlite")
+            .env("TODO_FILE", &sqlite_path)
+            .env("TODO_TZ", "UTC");
+        cmd
+    };
+
+    // The real clock, so the due and deleted dates carry sub-second parts
+    // unless they are truncated before being stored and journalled.
+    sqlite().args(["add", "Report", "Q1 numbers", "in 2 hours", "work"])
+        .assert()
+        .success();
+    sqlite().args(["delete", "Report"]).assert().success();
+
+    sqlite().arg("undo").assert().success().stdout(predicate::str::contains("Done."));
+    sqlite().args(["select", "*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Q1 numbers"));
+
+    sqlite().arg("redo")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("redo delete of #1: move #1 \"Report\" to the trash"));
+    sqlite().args(["select", "*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No tasks match"));
+    sqlite().args(["undo", "2"]).assert().success();
+    sqlite().args(["select", "*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No tasks match"));
+}
+
+#[test]
+fn test_damaged_journal_only_stops_undo() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+    let journal_path = temp_dir.path().join(format!("{TEST_JSON_FILE}.journal"));
+    let todolist = || {
+        let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+        cmd.env("TODO_FILE", &db_path).env("TODO_TZ", "UTC");
+        cmd
+    };
+
+    cmd.args(["add", "Report", "Q1 numbers", "1-1-2021 12:00", "work"])
+        .assert()
+        .success();
+    std::fs::write(&journal_path, "{not a journal").expect("Failed to damage the journal");
+
+    todolist().args(["select", "*"]).assert().success().stdout(predicate::str::contains("Report"));
+    todolist().arg("undo")
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains("undo journal").and(predicate::str::contains("is damaged")));
+
+    todolist().args(["add", "Slides", "For the report", "2-1-2021 12:00", "work"])
+        .assert()
+        .success();
+    let kept = std::fs::read_dir(temp_dir.path())
+        .expect("Failed to list the temp dir")
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().ends_with(".corrupt"))
+        .map(|entry| std::fs::read_to_string(entry.path()).expect("Failed to read the copy"))
+        .collect::<Vec<_>>();
+    assert_eq!(kept, ["{not a journal"]);
+
+    todolist().arg("undo")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("undo add of #2: remove #2"));
+    todolist().arg("undo").assert().success().stdout(predicate::str::contains("Nothing to undo."));
+}
+
+#[test]
+fn test_trash_restore_and_empty() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+    let todolist = || {
+        let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+        cmd.env("TODO_FILE", &db_path).env("TODO_TZ", "UTC");
+        cmd
+    };
+
+    cmd.args(["add", "Report", "Q1 numbers", "1-1-2021 12:00", "work"])
+        .assert()
+        .success();
+    todolist().args(["add", "Scratch", "Throwaway", "1-1-2021 12:00", "misc"]).assert().success();
+    todolist().args(["delete", "Report"]).assert().success().stdout(predicate::str::contains("todolist restore 1"));
+    todolist().args(["delete", "Scratch"]).assert().success();
+
+    todolist().args(["select", "*"]).assert().success().stdout(predicate::str::contains("No tasks match"));
+    todolist().args(["select", "--include-trash", "*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Q1 numbers").and(predicate::str::contains("deleted")));
+    todolist().args(["trash", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Report").and(predicate::str::contains("Scratch")));
+    todolist().args(["done", "Report"]).assert().code(3);
+
+    todolist().args(["restore", "Report"]).assert().success().stdout(predicate::str::contains("Task restored!"));
+    todolist().args(["trash", "empty", "--older-than", "30d"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Permanently removed 0 task(s)."));
+    todolist().env("TODO_NOW", "01-01-2099 00:00")
+        .args(["trash", "empty", "--older-than", "30d"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Permanently removed 1 task(s)."));
+    todolist().args(["trash", "list"]).assert().success().stdout(predicate::str::contains("The trash is empty."));
+    todolist().args(["trash", "empty", "--older-than", "soon"]).assert().code(5);
+
+    todolist().args(["select", "*"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Q1 numbers").and(predicate::str::contains("Throwaway").not()));
+}
+
+#[test]
+fn test_history_and_changed_since() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+    let todolist = |user: &str| {
+        let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+        cmd.env("TODO_FILE", &db_path).env("TODO_TZ", "UTC").env("TODO_USER", user);
+        cmd
+    };
+
+    cmd.env("TODO_USER", "alice")
+        .args(["add", "Report", "Q1 numbers", "15-03-2024 12:00", "work"])
+        .assert()
+        .success();
+    todolist("alice").args(["add", "Untouched", "Other", "15-03-2024 12:00", "work"]).assert().success();
+    todolist("bob").args(["update", "Report", "--date", "22-03-2024 12:00"]).assert().success();
+
+    todolist("carol").args(["history", "Report"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("alice  add  name none -> \"Report\""))
+        .stdout(predicate::str::contains("bob  update  date \"2024-03-15T12:00:00Z\" -> \"2024-03-22T12:00:00Z\""));
+    todolist("carol").args(["history", "Missing"]).assert().code(3);
+    todolist("carol").args(["history", "99"]).assert().code(3);
+
+    todolist("carol").args(["select", "changed since yesterday"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Report").and(predicate::str::contains("Untouched")));
+    todolist("carol").args(["select", "changed since tomorrow"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No tasks match"));
+}
+
+#[test]
+fn test_task_lifecycle_commands() {
+    let temp_dir = tempdir().expect("Failed to create a temporary directory");
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+    let version_3 = r#"{"version":3,"next_id":2,"tasks":{"1":{"id":1,"name":"Old","description":"","date":"2021-01-01T12:00:00Z","category":"","status":true}}}"#;
+    std::fs::write(&db_path, version_3).expect("Failed to write the database");
+    let todolist = || {
+        let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+        cmd.env("TODO_FILE", &db_path);
+        cmd
+    };
+
+    todolist().args(["select", "status = done"]).assert().success().stdout(predicate::str::contains("Old"));
+    todolist().args(["add", "Report", "Q1 numbers", "15-03-2024 12:00", "work"]).assert().success();
+    todolist().args(["start", "Report"]).assert().success().stdout(predicate::str::contains("Task #2 is now in_progress."));
+    todolist().args(["select", "status = off"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Report").and(predicate::str::contains("Old").not()));
+    todolist().args(["block", "2"]).assert().success();
+    todolist().args(["select", "status in (blocked, waiting)"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Report").and(predicate::str::contains("status: blocked")));
+
+    todolist().args(["cancel", "Report"]).assert().success();
+    todolist().args(["start", "Report"])
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains("Task #2 cannot go from cancelled to in_progress; it can go to pending"));
+    todolist().args(["reopen", "Report"]).assert().success().stdout(predicate::str::contains("Task reopened!"));
+
+    todolist().env("TODO_TRANSITIONS", "pending: done").args(["wait", "Report"]).assert().code(4);
+    todolist().args(["update", "Report", "--status", "finished"]).assert().code(2);
+}
+
+#[test]
+fn test_reopen_a_done_task() {
+    let (mut cmd, temp_dir) = create_todo_list_command();
+    let db_path = temp_dir.path().join(TEST_JSON_FILE);
+    let todolist = || {
+        let mut cmd = Command::cargo_bin("todolist").expect("Failed to find the 'todolist' binary");
+        cmd.env("TODO_FILE", &db_path)
+            .env("TODO_USER", "alice")
+            .env("TODO_TZ", "UTC")
+            .env("TODO_NOW", "20-03-2024 09:30");
+        cmd
+    };
+
+    cmd.args(["add", "Report", "Q1 numbers", "15-03-2024 12:00", "work"]).assert().success();
+    todolist().args(["reopen", "Report"])
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains("task #1 is pending; only done or cancelled tasks can be reopened"));
+
+    todolist().args(["done", "Report"]).assert().success();
+    todolist().args(["undone", "1"]).assert().success().stdout(predicate::str::contains("Task reopened!"));
+    todolist().args(["select", "status = pending"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Report").and(predicate::str::contains("reopened: 2024-03-20T09:30:00Z")));
+    todolist().args(["history", "Report"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("alice  reopen  status \"done\" -> \"pending\""));
+}